@@ -0,0 +1,94 @@
+use std::str::FromStr;
+
+use axum::http::Method;
+use chrono::{Duration, Utc};
+use uuid::Uuid;
+
+use crate::auth::{AuthProvider, Role, User};
+use crate::error::AppError;
+use crate::models::mongo::{ApiToken, TokenScope};
+use crate::session::{hash_secret, random_token};
+use crate::AppStateStruct;
+
+pub const API_TOKEN_PREFIX: &str = "smt_";
+pub const MAX_TOKEN_LIFETIME_DAYS: u32 = 3650;
+
+pub async fn create_api_token(
+    state: &AppStateStruct,
+    user: &User,
+    name: String,
+    scope: TokenScope,
+    expires_in_days: Option<i64>,
+) -> anyhow::Result<(ApiToken, String)> {
+    let secret = random_token();
+    let now = Utc::now();
+    let token = ApiToken {
+        uid: Uuid::new_v4().into(),
+        email: user.email.clone(),
+        sub: user.sub.clone(),
+        name,
+        scope,
+        token_hash: hash_secret(&secret),
+        created: now,
+        expires: expires_in_days.map(|days| now + Duration::days(days)),
+    };
+    state.store.create_api_token(&token).await?;
+
+    let secret = format!("{}{}.{}", API_TOKEN_PREFIX, token.uid, secret);
+    Ok((token, secret))
+}
+
+// read-only tokens are rejected for anything but safe methods
+pub async fn authenticate(
+    state: &AppStateStruct,
+    token: &str,
+    method: &Method,
+) -> Result<User, AppError> {
+    let invalid = || AppError::new(401, "invalid or expired token");
+
+    let Some((uid, secret)) = token
+        .strip_prefix(API_TOKEN_PREFIX)
+        .and_then(|t| t.split_once('.'))
+    else {
+        return Err(invalid());
+    };
+    let Ok(uid) = Uuid::from_str(uid) else {
+        return Err(invalid());
+    };
+    let Some(token) = state.store.get_api_token(&uid.into()).await? else {
+        return Err(invalid());
+    };
+    if !token.is_active() || token.token_hash != hash_secret(secret) {
+        return Err(invalid());
+    }
+
+    // the owner may have lost access since the token was created
+    let owner_allowed = match state.config.auth_provider {
+        AuthProvider::Oidc => state.policy.allows(&token.email),
+        AuthProvider::Local => state.store.get_local_account(&token.email).await?.is_some(),
+    };
+    if !owner_allowed {
+        return Err(invalid());
+    }
+
+    if token.scope == TokenScope::READ && !method.is_safe() {
+        return Err(AppError::new(403, "token is read-only"));
+    }
+
+    let role = state.roles.resolve(state, &token.email).await?;
+    let role = match token.scope {
+        TokenScope::ADMIN => role,
+        TokenScope::WRITE => role.min(Role::USER),
+        TokenScope::READ => Role::VIEWER,
+    };
+
+    Ok(User {
+        sub: token.sub,
+        email: token.email,
+        dn: state.policy.organisation.clone(),
+        exp: token.expires.map_or(0, |e| e.timestamp() as usize),
+        role,
+        sid: Uuid::nil(),
+        token: Some(uid),
+    })
+}
@@ -1,27 +1,43 @@
+use std::collections::HashMap;
 use std::ops::Deref;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex, RwLock};
 
 use anyhow::anyhow;
 use async_trait::async_trait;
 use axum::extract::FromRequestParts;
+use axum::http::header::AUTHORIZATION;
 use axum::http::request::Parts;
-use chrono::Duration;
-use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use chrono::{DateTime, Duration, Utc};
 use openidconnect::core::{
-    CoreAuthenticationFlow, CoreClient, CoreGenderClaim, CoreProviderMetadata,
+    CoreAuthDisplay, CoreAuthPrompt, CoreAuthenticationFlow, CoreClaimName, CoreClaimType,
+    CoreClientAuthMethod, CoreErrorResponseType, CoreGenderClaim, CoreGrantType, CoreJsonWebKey,
+    CoreJsonWebKeyType, CoreJsonWebKeyUse, CoreJweContentEncryptionAlgorithm,
+    CoreJweKeyManagementAlgorithm, CoreJwsSigningAlgorithm, CoreResponseMode, CoreResponseType,
+    CoreRevocableToken, CoreRevocationErrorResponse, CoreSubjectIdentifierType,
+    CoreTokenIntrospectionResponse, CoreTokenType,
 };
 use openidconnect::{
-    AuthorizationCode, ClientId, ClientSecret, CsrfToken, EmptyAdditionalClaims, IdTokenClaims,
-    IssuerUrl, Nonce, RedirectUrl, Scope,
+    AdditionalClaims, AdditionalProviderMetadata, AuthorizationCode, ClientId, ClientSecret,
+    CsrfToken, EmptyExtraTokenFields, IdTokenClaims, IdTokenFields, IssuerUrl, Nonce,
+    PkceCodeChallenge, PkceCodeChallengeMethod, PkceCodeVerifier, RedirectUrl, Scope,
+    StandardErrorResponse, StandardTokenResponse,
 };
 use retainer::{Cache, CacheExpiration};
+use serde::de::IntoDeserializer;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use tokio::select;
 use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
+use uuid::Uuid;
 
+use crate::api_token::API_TOKEN_PREFIX;
 use crate::error::AppError;
-use crate::queries::mongo::MongoQueries;
-use crate::AppState;
+use crate::misc::normalize_email;
+use crate::models::mongo::{Change, ChangeType, GroupRole, Sponsor, UserRole};
+use crate::queries::store::SponsorStore;
+use crate::session::SESSION_COOKIE;
+use crate::{api_token, session, AppState, AppStateStruct};
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct User {
@@ -30,15 +46,80 @@ pub struct User {
     pub dn: String,
     pub exp: usize,
     pub role: Role,
+    pub sid: Uuid,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token: Option<Uuid>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Hash, Eq)]
+// ordered from least to most privileged
+#[allow(non_camel_case_types)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Hash, Eq, PartialOrd, Ord)]
 pub enum Role {
+    VIEWER,
+    FAVOUR_MANAGER,
     USER,
     ADMIN,
 }
 
-pub struct RequireAdmin(pub User);
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Permission {
+    TickFavours,
+    EditSponsors,
+    ManageSettings,
+}
+
+impl Role {
+    pub fn has(&self, permission: Permission) -> bool {
+        match permission {
+            Permission::TickFavours => *self >= Role::FAVOUR_MANAGER,
+            Permission::EditSponsors => *self >= Role::USER,
+            Permission::ManageSettings => *self == Role::ADMIN,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum AuthProvider {
+    #[default]
+    Oidc,
+    Local,
+}
+
+pub struct AccessPolicy {
+    pub allowed_domains: Vec<String>,
+    pub allowed_emails: Vec<String>,
+    pub organisation: String,
+    pub default_role: Role,
+    pub owners_only_edit: bool,
+}
+
+impl AccessPolicy {
+    pub fn may_edit(&self, user: &User, sponsor: &Sponsor) -> bool {
+        !self.owners_only_edit
+            || user.role == Role::ADMIN
+            || sponsor.owners.contains(&normalize_email(&user.email))
+    }
+
+    pub fn allows(&self, email: &str) -> bool {
+        let email = email.to_lowercase();
+        if self
+            .allowed_emails
+            .iter()
+            .any(|e| e.to_lowercase() == email)
+        {
+            return true;
+        }
+
+        let Some((_, domain)) = email.rsplit_once('@') else {
+            return false;
+        };
+        self.allowed_domains.iter().any(|d| {
+            let d = d.trim_start_matches('@').to_lowercase();
+            d == "*" || d == domain
+        })
+    }
+}
 
 #[async_trait]
 impl FromRequestParts<AppState> for User {
@@ -48,111 +129,361 @@ impl FromRequestParts<AppState> for User {
         request: &mut Parts,
         state: &AppState,
     ) -> Result<Self, Self::Rejection> {
-        let Some(auth_header) = request.headers.get("Authorization") else {
-            return Err(AppError::new(401, "unauthorized"));
+        let token = match request.headers.get(AUTHORIZATION) {
+            Some(auth_header) => {
+                let Ok(auth_header) = auth_header.to_str() else {
+                    return Err(AppError::new(400, "invalid auth header"));
+                };
+                let Some(token) = auth_header.strip_prefix("Bearer ") else {
+                    return Err(AppError::new(400, "invalid auth header"));
+                };
+                if token.starts_with(API_TOKEN_PREFIX) {
+                    return api_token::authenticate(state, token, &request.method).await;
+                }
+                token
+            }
+            None => {
+                let Some(token) = session::get_cookie(&request.headers, SESSION_COOKIE) else {
+                    return Err(AppError::new(401, "unauthorized"));
+                };
+                // browsers attach cookies to cross site requests, bearer tokens they don't
+                if !request.method.is_safe() && !session::csrf_valid(&request.headers) {
+                    return Err(AppError::new(403, "invalid csrf token"));
+                }
+                token
+            }
         };
-        let Ok(auth_header) = auth_header.to_str() else {
-            return Err(AppError::new(400, "invalid auth header"));
+
+        let Ok(mut user) = state.jwt.validate_jwt(token) else {
+            return Err(AppError::new(401, "invalid or expired token"));
         };
 
-        if !auth_header.starts_with("Bearer ") {
-            return Err(AppError::new(400, "invalid auth header"));
+        match state.store.get_session(&user.sid.into()).await? {
+            Some(session) if session.is_active() => {}
+            _ => return Err(AppError::new(401, "session revoked")),
         }
-        let auth_header = &auth_header[7..];
 
-        let Ok(user) = state.jwt.validate_jwt(auth_header) else {
-            return Err(AppError::new(400, "invalid auth header"));
-        };
+        // the role in the token may be outdated, see RoleCache
+        user.role = state.roles.resolve(state, &user.email).await?;
 
         Ok(user)
     }
 }
 
-#[async_trait]
-impl FromRequestParts<AppState> for RequireAdmin {
-    type Rejection = AppError;
+macro_rules! require_permission {
+    ($($(#[$meta:meta])* $name:ident => $permission:expr;)*) => {
+        $(
+            $(#[$meta])*
+            pub struct $name(pub User);
 
-    async fn from_request_parts(
-        parts: &mut Parts,
-        state: &AppState,
-    ) -> Result<Self, Self::Rejection> {
-        let user = User::from_request_parts(parts, state).await?;
+            #[async_trait]
+            impl FromRequestParts<AppState> for $name {
+                type Rejection = AppError;
+
+                async fn from_request_parts(
+                    parts: &mut Parts,
+                    state: &AppState,
+                ) -> Result<Self, Self::Rejection> {
+                    let user = User::from_request_parts(parts, state).await?;
+
+                    if !user.role.has($permission) {
+                        return Err(AppError::new(403, "forbidden"));
+                    }
+
+                    Ok($name(user))
+                }
+            }
+
+            impl Deref for $name {
+                type Target = User;
+
+                fn deref(&self) -> &Self::Target {
+                    &self.0
+                }
+            }
+        )*
+    };
+}
+
+require_permission! {
+    RequireTickFavours => Permission::TickFavours;
+    RequireEdit => Permission::EditSponsors;
+    RequireAdmin => Permission::ManageSettings;
+}
 
-        if !matches!(user.role, Role::ADMIN) {
-            return Err(AppError::new(403, "forbidden"));
+const ROLE_CACHE_TTL_MILLIS: u64 = 5000;
+
+pub struct RoleCache {
+    roles: Arc<Cache<String, Role>>,
+    cancel_token: CancellationToken,
+}
+
+impl RoleCache {
+    pub fn new() -> Self {
+        let cache = Arc::new(Cache::new());
+        let cancel_token = CancellationToken::new();
+
+        let cloned_cache = cache.clone();
+        let cloned_cancel_token = cancel_token.clone();
+        tokio::spawn(async move {
+            select! {
+                _ = cloned_cancel_token.cancelled() => {
+
+                }
+                _ = cloned_cache.monitor(4, 0.25, Duration::seconds(10).to_std().unwrap()) => {
+
+                }
+            }
+        });
+
+        Self {
+            roles: cache,
+            cancel_token,
         }
+    }
+
+    pub async fn resolve(&self, state: &AppStateStruct, email: &str) -> anyhow::Result<Role> {
+        let email = normalize_email(email);
+        if let Some(role) = self.roles.get(&email).await {
+            return Ok(*role);
+        }
+
+        let role = match state.store.get_user_role(&email).await? {
+            Some(role) => role,
+            None => state
+                .store
+                .get_group_role(&email)
+                .await?
+                .filter(GroupRole::is_fresh)
+                .map_or(state.policy.default_role, |g| g.role),
+        };
+        self.roles
+            .insert(email, role, CacheExpiration::from(ROLE_CACHE_TTL_MILLIS))
+            .await;
+
+        Ok(role)
+    }
+
+    pub async fn invalidate(&self, email: &str) {
+        self.roles.remove(&normalize_email(email)).await;
+    }
+}
 
-        Ok(RequireAdmin(user))
+impl Default for RoleCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for RoleCache {
+    fn drop(&mut self) {
+        self.cancel_token.cancel();
     }
 }
 
-impl Deref for RequireAdmin {
-    type Target = User;
+// only on a fresh install, demoted bootstrap admins have no role either
+pub async fn seed_bootstrap_admins(
+    store: &dyn SponsorStore,
+    emails: &[String],
+) -> anyhow::Result<()> {
+    if !store.get_all_roles().await?.is_empty() {
+        return Ok(());
+    }
 
-    fn deref(&self) -> &Self::Target {
-        &self.0
+    for email in emails
+        .iter()
+        .map(|e| normalize_email(e))
+        .filter(|e| !e.is_empty())
+    {
+        let role = UserRole {
+            email: email.clone(),
+            role: Role::ADMIN,
+        };
+        store
+            .add_change(&Change::new(
+                "bootstrap",
+                ChangeType::ChangeUserRole(role.clone()),
+            ))
+            .await?;
+        store.add_or_update_role(&role).await?;
+        info!("Bootstrapped admin {}", email);
     }
+
+    Ok(())
 }
 
-pub struct JwtInstance {
-    encoding_key: EncodingKey,
-    decoding_key: DecodingKey,
-    header: Header,
-    validation: Validation,
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ExtraClaims {
+    #[serde(flatten)]
+    claims: HashMap<String, Value>,
 }
 
-impl JwtInstance {
-    pub fn new(secret: &str) -> Self {
-        Self {
-            encoding_key: EncodingKey::from_secret(secret.as_ref()),
-            decoding_key: DecodingKey::from_secret(secret.as_ref()),
-            header: Header::new(Algorithm::HS512),
-            validation: Validation::new(Algorithm::HS512),
+impl AdditionalClaims for ExtraClaims {}
+
+type OidcClient = openidconnect::Client<
+    ExtraClaims,
+    CoreAuthDisplay,
+    CoreGenderClaim,
+    CoreJweContentEncryptionAlgorithm,
+    CoreJwsSigningAlgorithm,
+    CoreJsonWebKeyType,
+    CoreJsonWebKeyUse,
+    CoreJsonWebKey,
+    CoreAuthPrompt,
+    StandardErrorResponse<CoreErrorResponseType>,
+    StandardTokenResponse<
+        IdTokenFields<
+            ExtraClaims,
+            EmptyExtraTokenFields,
+            CoreGenderClaim,
+            CoreJweContentEncryptionAlgorithm,
+            CoreJwsSigningAlgorithm,
+            CoreJsonWebKeyType,
+        >,
+        CoreTokenType,
+    >,
+    CoreTokenType,
+    CoreTokenIntrospectionResponse,
+    CoreRevocableToken,
+    CoreRevocationErrorResponse,
+>;
+
+pub type TokenClaims = IdTokenClaims<ExtraClaims, CoreGenderClaim>;
+
+// groups are only read at login, refreshing fails once the group role is older
+pub const GROUP_ROLE_MAX_AGE_HOURS: i64 = 12;
+
+pub struct GroupRoles {
+    // nested claims are separated by dots, e.g. realm_access.roles
+    claim: String,
+    roles: HashMap<String, Role>,
+}
+
+impl GroupRoles {
+    pub fn parse(claim: String, mappings: &[String]) -> anyhow::Result<Self> {
+        let mut roles = HashMap::new();
+        for mapping in mappings.iter().map(|m| m.trim()).filter(|m| !m.is_empty()) {
+            let (group, role) = mapping
+                .rsplit_once('=')
+                .ok_or(anyhow!("group mapping {} is not group=ROLE", mapping))?;
+            let role = Role::deserialize(role.trim().into_deserializer()).map_err(
+                |e: serde::de::value::Error| anyhow!("group mapping {}: {}", mapping, e),
+            )?;
+            roles.insert(group.trim().to_string(), role);
         }
-    }
 
-    pub fn create_jwt(&self, user: &User) -> anyhow::Result<String> {
-        Ok(jsonwebtoken::encode(
-            &self.header,
-            &user,
-            &self.encoding_key,
-        )?)
+        Ok(Self { claim, roles })
     }
 
-    pub fn validate_jwt(&self, jwt: &str) -> anyhow::Result<User> {
-        Ok(jsonwebtoken::decode::<User>(jwt, &self.decoding_key, &self.validation)?.claims)
+    fn role_of(&self, claims: &ExtraClaims) -> Option<Role> {
+        let mut parts = self.claim.split('.');
+        let mut value = claims.claims.get(parts.next()?)?;
+        for part in parts {
+            value = value.get(part)?;
+        }
+
+        let groups = match value {
+            Value::Array(groups) => groups.iter().filter_map(Value::as_str).collect(),
+            Value::String(group) => vec![group.as_str()],
+            _ => Vec::new(),
+        };
+        groups
+            .into_iter()
+            .filter_map(|g| self.roles.get(g))
+            .max()
+            .copied()
     }
 }
 
+const DISCOVERY_REFRESH_SECS: u64 = 60 * 60;
+const DISCOVERY_RETRY_MIN_SECS: u64 = 1;
+const DISCOVERY_RETRY_MAX_SECS: u64 = 5 * 60;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct PkceProviderMetadata {
+    code_challenge_methods_supported: Option<Vec<PkceCodeChallengeMethod>>,
+}
+
+impl AdditionalProviderMetadata for PkceProviderMetadata {}
+
+type ProviderMetadata = openidconnect::ProviderMetadata<
+    PkceProviderMetadata,
+    CoreAuthDisplay,
+    CoreClientAuthMethod,
+    CoreClaimName,
+    CoreClaimType,
+    CoreGrantType,
+    CoreJweContentEncryptionAlgorithm,
+    CoreJweKeyManagementAlgorithm,
+    CoreJwsSigningAlgorithm,
+    CoreJsonWebKeyType,
+    CoreJsonWebKeyUse,
+    CoreJsonWebKey,
+    CoreResponseMode,
+    CoreResponseType,
+    CoreSubjectIdentifierType,
+>;
+
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct OidcStatus {
+    pub ready: bool,
+    #[serde(rename = "lastDiscovery")]
+    pub last_discovery: Option<DateTime<Utc>>,
+    #[serde(rename = "lastError")]
+    pub last_error: Option<String>,
+    #[serde(rename = "failedAttempts")]
+    pub failed_attempts: u32,
+    #[serde(rename = "pkceSupported")]
+    pub pkce_supported: bool,
+}
+
+struct OidcConfig {
+    issuer_url: IssuerUrl,
+    client_id: ClientId,
+    client_secret: ClientSecret,
+    redirect_url: RedirectUrl,
+}
+
+#[derive(Default)]
+struct Discovery {
+    client: RwLock<Option<Arc<OidcClient>>>,
+    status: Mutex<OidcStatus>,
+}
+
 pub struct OpenIdInstance {
-    client: CoreClient,
-    sessions: Arc<Cache<String, String>>,
+    discovery: Arc<Discovery>,
+    extra_scopes: Vec<String>,
+    group_roles: Option<GroupRoles>,
+    require_pkce: bool,
+    sessions: Arc<Cache<String, PendingLogin>>,
     cancel_token: CancellationToken,
 }
 
-pub type TokenClaims = IdTokenClaims<EmptyAdditionalClaims, CoreGenderClaim>;
+#[derive(Clone)]
+struct PendingLogin {
+    nonce: String,
+    pkce_verifier: Option<String>,
+    redirect: Option<String>,
+}
 
 impl OpenIdInstance {
-    pub async fn new<S: Into<String>>(
+    pub fn new<S: Into<String>>(
         client_id: S,
         client_secret: S,
         issuer_url: S,
         hostname: S,
+        extra_scopes: Vec<String>,
+        group_roles: Option<GroupRoles>,
+        require_pkce: bool,
     ) -> anyhow::Result<Self> {
-        let issuer_url = IssuerUrl::new(issuer_url.into())?;
-        let metadata = CoreProviderMetadata::discover_async(
-            issuer_url,
-            openidconnect::reqwest::async_http_client,
-        )
-        .await?;
-
-        let client = CoreClient::from_provider_metadata(
-            metadata,
-            ClientId::new(client_id.into()),
-            Some(ClientSecret::new(client_secret.into())),
-        )
-        .set_redirect_uri(RedirectUrl::new(hostname.into() + "/api/login/code")?);
+        let config = OidcConfig {
+            issuer_url: IssuerUrl::new(issuer_url.into())?,
+            client_id: ClientId::new(client_id.into()),
+            client_secret: ClientSecret::new(client_secret.into()),
+            redirect_url: RedirectUrl::new(hostname.into() + "/api/login/code")?,
+        };
 
+        let discovery = Arc::new(Discovery::default());
         let cache = Arc::new(Cache::new());
         let cancel_token = CancellationToken::new();
 
@@ -169,16 +500,50 @@ impl OpenIdInstance {
             }
         });
 
+        let cloned_discovery = discovery.clone();
+        let cloned_cancel_token = cancel_token.clone();
+        tokio::spawn(async move {
+            select! {
+                _ = cloned_cancel_token.cancelled() => {
+
+                }
+                _ = discover_loop(&cloned_discovery, &config) => {
+
+                }
+            }
+        });
+
         Ok(Self {
-            client,
+            discovery,
+            extra_scopes,
+            group_roles,
+            require_pkce,
             sessions: cache,
             cancel_token,
         })
     }
 
-    pub async fn create_auth_url(&self) -> String {
-        let (url, csrf, nonce) = self
+    pub fn status(&self) -> OidcStatus {
+        self.discovery.status.lock().unwrap().clone()
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.discovery.client.read().unwrap().is_some()
+    }
+
+    fn client(&self) -> anyhow::Result<Arc<OidcClient>> {
+        self.discovery
             .client
+            .read()
+            .unwrap()
+            .clone()
+            .ok_or(anyhow!("identity provider not discovered yet"))
+    }
+
+    // redirect must already be checked with is_same_origin_path
+    pub async fn create_auth_url(&self, redirect: Option<String>) -> anyhow::Result<String> {
+        let client = self.client()?;
+        let mut request = client
             .authorize_url(
                 CoreAuthenticationFlow::AuthorizationCode,
                 CsrfToken::new_random,
@@ -186,36 +551,66 @@ impl OpenIdInstance {
             )
             .add_scope(Scope::new("email".to_string()))
             .add_scope(Scope::new("profile".to_string()))
-            .url();
+            .add_scopes(
+                self.extra_scopes
+                    .iter()
+                    .filter(|s| !s.is_empty())
+                    .cloned()
+                    .map(Scope::new),
+            );
+
+        let mut pkce_verifier = None;
+        if self.require_pkce || self.discovery.status.lock().unwrap().pkce_supported {
+            let (challenge, verifier) = PkceCodeChallenge::new_random_sha256();
+            request = request.set_pkce_challenge(challenge);
+            pkce_verifier = Some(verifier.secret().clone());
+        }
+
+        let (url, csrf, nonce) = request.url();
 
         self.sessions
             .insert(
                 csrf.secret().clone(),
-                nonce.secret().clone(),
+                PendingLogin {
+                    nonce: nonce.secret().clone(),
+                    pkce_verifier,
+                    redirect,
+                },
                 CacheExpiration::from(60000),
             )
             .await;
 
-        url.to_string()
+        Ok(url.to_string())
     }
 
-    pub async fn fetch_token(&self, code: String, state: String) -> anyhow::Result<TokenClaims> {
-        let nonce = self.sessions.get(&state).await;
+    pub async fn fetch_token(
+        &self,
+        code: String,
+        state: String,
+    ) -> anyhow::Result<(TokenClaims, Option<String>)> {
+        let pending = self.sessions.get(&state).await;
 
-        if nonce.is_none() {
+        if pending.is_none() {
             return Err(anyhow!("invalid state"));
         }
 
-        let nonce = nonce.unwrap().clone();
+        let PendingLogin {
+            nonce,
+            pkce_verifier,
+            redirect,
+        } = pending.unwrap().clone();
 
-        let res = self
-            .client
-            .exchange_code(AuthorizationCode::new(code))
+        let client = self.client()?;
+        let mut request = client.exchange_code(AuthorizationCode::new(code));
+        if let Some(verifier) = pkce_verifier {
+            request = request.set_pkce_verifier(PkceCodeVerifier::new(verifier));
+        }
+        let res = request
             .request_async(openidconnect::reqwest::async_http_client)
             .await
             .map_err(|e| anyhow!("invalid exchange code: {:?}", e))?;
 
-        let token_verifier = self.client.id_token_verifier();
+        let token_verifier = client.id_token_verifier();
         let token_claims = res
             .extra_fields()
             .id_token()
@@ -223,11 +618,13 @@ impl OpenIdInstance {
             .claims(&token_verifier, &Nonce::new(nonce))?
             .clone();
 
-        Ok(token_claims)
+        Ok((token_claims, redirect))
     }
 
     pub async fn user_from_claims(
-        mongo: &MongoQueries,
+        &self,
+        store: &dyn SponsorStore,
+        policy: &AccessPolicy,
         claims: TokenClaims,
     ) -> anyhow::Result<User> {
         let name = claims
@@ -236,21 +633,33 @@ impl OpenIdInstance {
             .get(None)
             .ok_or(anyhow!("username locale invalid?"))?
             .to_string();
-        let email = claims.email().ok_or(anyhow!("email empty"))?.to_string();
-        let exp = (chrono::Local::now() + Duration::days(1)).timestamp() as usize;
+        let email = normalize_email(claims.email().ok_or(anyhow!("email empty"))?);
 
-        if !email.ends_with("@greenbear.berlin") && email != "zargor3@gmail.com" {
+        if !policy.allows(&email) {
             return Err(anyhow!("third parties are not allowed to access"));
         }
 
-        let role = mongo.get_user_role(&email).await?.unwrap_or(Role::USER);
+        let mut group_role = None;
+        if let Some(group_roles) = &self.group_roles {
+            group_role = group_roles.role_of(claims.additional_claims());
+            store.set_group_role(&email, group_role).await?;
+        }
+
+        let role = store
+            .get_user_role(&email)
+            .await?
+            .or(group_role)
+            .unwrap_or(policy.default_role);
 
         Ok(User {
             sub: name,
             email,
-            dn: "greenBEAR".to_string(),
-            exp,
+            dn: policy.organisation.clone(),
+            // both set by session::start_session
+            exp: 0,
             role,
+            sid: Uuid::nil(),
+            token: None,
         })
     }
 }
@@ -260,3 +669,166 @@ impl Drop for OpenIdInstance {
         self.cancel_token.cancel();
     }
 }
+
+async fn discover_loop(discovery: &Discovery, config: &OidcConfig) {
+    let mut retry_secs = DISCOVERY_RETRY_MIN_SECS;
+    loop {
+        let wait_secs = match discover(config).await {
+            Ok((client, pkce_supported)) => {
+                *discovery.client.write().unwrap() = Some(Arc::new(client));
+                let mut status = discovery.status.lock().unwrap();
+                status.ready = true;
+                status.pkce_supported = pkce_supported;
+                status.last_discovery = Some(Utc::now());
+                status.last_error = None;
+                status.failed_attempts = 0;
+                retry_secs = DISCOVERY_RETRY_MIN_SECS;
+                DISCOVERY_REFRESH_SECS
+            }
+            Err(e) => {
+                // a previously discovered client stays usable
+                warn!(
+                    "OIDC discovery failed, retrying in {}s: {:?}",
+                    retry_secs, e
+                );
+                let mut status = discovery.status.lock().unwrap();
+                status.last_error = Some(format!("{:#}", e));
+                status.failed_attempts += 1;
+                let wait_secs = retry_secs;
+                retry_secs = (retry_secs * 2).min(DISCOVERY_RETRY_MAX_SECS);
+                wait_secs
+            }
+        };
+
+        tokio::time::sleep(std::time::Duration::from_secs(wait_secs)).await;
+    }
+}
+
+async fn discover(config: &OidcConfig) -> anyhow::Result<(OidcClient, bool)> {
+    let metadata = ProviderMetadata::discover_async(
+        config.issuer_url.clone(),
+        openidconnect::reqwest::async_http_client,
+    )
+    .await?;
+
+    let pkce_supported = metadata
+        .additional_metadata()
+        .code_challenge_methods_supported
+        .iter()
+        .flatten()
+        .any(|method| method.as_str() == "S256");
+
+    let client = OidcClient::from_provider_metadata(
+        metadata,
+        config.client_id.clone(),
+        Some(config.client_secret.clone()),
+    )
+    .set_redirect_uri(config.redirect_url.clone());
+
+    Ok((client, pkce_supported))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::{AccessPolicy, ExtraClaims, GroupRoles, Role};
+
+    fn policy(domains: &[&str], emails: &[&str]) -> AccessPolicy {
+        AccessPolicy {
+            allowed_domains: domains.iter().map(|d| d.to_string()).collect(),
+            allowed_emails: emails.iter().map(|e| e.to_string()).collect(),
+            organisation: "Sponsormanager".to_string(),
+            default_role: Role::USER,
+            owners_only_edit: false,
+        }
+    }
+
+    #[test]
+    fn allows_matching_domains_only() {
+        let policy = policy(&["example.org", "@team.example.org"], &[]);
+
+        assert!(policy.allows("alice@example.org"));
+        assert!(policy.allows("bob@team.example.org"));
+        assert!(!policy.allows("mallory@evil.org"));
+        assert!(!policy.allows("mallory@sub.example.org"));
+        assert!(!policy.allows("mallory@example.org.evil.org"));
+        assert!(!policy.allows("example.org"));
+    }
+
+    #[test]
+    fn allows_ignores_case() {
+        let policy = policy(&["Example.ORG"], &["Carol@Other.org"]);
+
+        assert!(policy.allows("ALICE@example.org"));
+        assert!(policy.allows("carol@other.ORG"));
+        assert!(!policy.allows("dave@other.org"));
+    }
+
+    #[test]
+    fn allows_nobody_with_empty_allowlist() {
+        let policy = policy(&[], &[]);
+
+        assert!(!policy.allows("alice@example.org"));
+        assert!(!policy.allows("alice@"));
+    }
+
+    #[test]
+    fn allows_every_domain_with_wildcard() {
+        assert!(policy(&["*"], &[]).allows("anyone@anywhere.org"));
+    }
+
+    fn group_roles(claim: &str, mappings: &[&str]) -> GroupRoles {
+        let mappings: Vec<String> = mappings.iter().map(|m| m.to_string()).collect();
+        GroupRoles::parse(claim.to_string(), &mappings).unwrap()
+    }
+
+    fn claims(value: Value) -> ExtraClaims {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn parses_group_mappings() {
+        let roles = group_roles("groups", &[" admins = ADMIN ", "", "team=a=USER"]);
+
+        assert_eq!(roles.roles.len(), 2);
+        assert_eq!(roles.roles["admins"], Role::ADMIN);
+        assert_eq!(roles.roles["team=a"], Role::USER);
+    }
+
+    #[test]
+    fn rejects_invalid_group_mappings() {
+        let parse = |mapping: &str| GroupRoles::parse("groups".to_string(), &[mapping.to_string()]);
+
+        assert!(parse("admins").is_err());
+        assert!(parse("admins=OWNER").is_err());
+        assert!(parse("admins=admin").is_err());
+    }
+
+    #[test]
+    fn picks_highest_group_role() {
+        let roles = group_roles("groups", &["viewers=VIEWER", "admins=ADMIN", "users=USER"]);
+
+        let role = roles.role_of(&claims(json!({"groups": ["viewers", "admins", "users"]})));
+        assert_eq!(role, Some(Role::ADMIN));
+        let role = roles.role_of(&claims(json!({"groups": ["viewers", "other"]})));
+        assert_eq!(role, Some(Role::VIEWER));
+        let role = roles.role_of(&claims(json!({"groups": "users"})));
+        assert_eq!(role, Some(Role::USER));
+        assert_eq!(roles.role_of(&claims(json!({"groups": ["other"]}))), None);
+        assert_eq!(roles.role_of(&claims(json!({"groups": 1}))), None);
+        assert_eq!(roles.role_of(&claims(json!({}))), None);
+    }
+
+    #[test]
+    fn reads_nested_group_claim() {
+        let roles = group_roles("realm_access.roles", &["admins=ADMIN"]);
+
+        let role = roles.role_of(&claims(json!({"realm_access": {"roles": ["admins"]}})));
+        assert_eq!(role, Some(Role::ADMIN));
+        assert_eq!(
+            roles.role_of(&claims(json!({"realm_access": ["admins"]}))),
+            None
+        );
+    }
+}
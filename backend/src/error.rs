@@ -1,6 +1,6 @@
 use axum::http::StatusCode;
-use axum::Json;
 use axum::response::{IntoResponse, Response};
+use axum::Json;
 use serde_json::json;
 use tracing::error;
 
@@ -23,18 +23,18 @@ impl AppError {
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
         let status = StatusCode::from_u16(self.status).unwrap();
-        if status.is_server_error() && self.error.is_some() {
-            error!("error while serving request: {:?}", self.error.unwrap());
+        if let (true, Some(error)) = (status.is_server_error(), self.error) {
+            error!("error while serving request: {:?}", error);
         }
 
-        (
-            status,
-            Json(json!({"error": &self.display}))
-        ).into_response()
+        (status, Json(json!({"error": &self.display}))).into_response()
     }
 }
 
-impl<E> From<E> for AppError where E: Into<anyhow::Error> {
+impl<E> From<E> for AppError
+where
+    E: Into<anyhow::Error>,
+{
     fn from(err: E) -> Self {
         let error = err.into();
         AppError {
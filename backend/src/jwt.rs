@@ -0,0 +1,248 @@
+use anyhow::{anyhow, bail};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use ring::rsa::PublicKeyComponents;
+use ring::signature::{Ed25519KeyPair, KeyPair, RsaKeyPair};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+use crate::auth::User;
+
+pub struct JwtInstance {
+    signing_key: JwtKey,
+    header: Header,
+    previous_keys: Vec<JwtKey>,
+}
+
+impl JwtInstance {
+    pub fn new(signing_key: JwtKey, previous_keys: Vec<JwtKey>) -> Self {
+        let mut header = Header::new(signing_key.algorithm);
+        header.kid = Some(signing_key.kid.clone());
+
+        Self {
+            signing_key,
+            header,
+            previous_keys,
+        }
+    }
+
+    pub fn create_jwt(&self, user: &User) -> anyhow::Result<String> {
+        Ok(jsonwebtoken::encode(
+            &self.header,
+            &user,
+            &self.signing_key.encoding_key,
+        )?)
+    }
+
+    pub fn validate_jwt(&self, jwt: &str) -> anyhow::Result<User> {
+        let header = jsonwebtoken::decode_header(jwt)?;
+
+        // tokens without kid were issued before key ids existed, try every key of the algorithm
+        let mut error = None;
+        for key in self.keys().filter(|key| {
+            key.algorithm == header.alg && header.kid.as_ref().is_none_or(|kid| *kid == key.kid)
+        }) {
+            match jsonwebtoken::decode::<User>(jwt, &key.decoding_key, &key.validation) {
+                Ok(data) => return Ok(data.claims),
+                Err(e) => error = Some(e),
+            }
+        }
+
+        Err(error.map_or_else(|| anyhow!("no key matches the token"), Into::into))
+    }
+
+    pub fn jwks(&self) -> Value {
+        let keys: Vec<&Value> = self.keys().filter_map(|key| key.jwk.as_ref()).collect();
+        json!({ "keys": keys })
+    }
+
+    fn keys(&self) -> impl Iterator<Item = &JwtKey> {
+        std::iter::once(&self.signing_key).chain(&self.previous_keys)
+    }
+}
+
+pub struct JwtKey {
+    kid: String,
+    algorithm: Algorithm,
+    encoding_key: EncodingKey,
+    decoding_key: DecodingKey,
+    validation: Validation,
+    jwk: Option<Value>,
+}
+
+impl JwtKey {
+    pub fn from_secret(secret: &str) -> Self {
+        Self::new(
+            derive_kid(secret.as_bytes()),
+            Algorithm::HS512,
+            EncodingKey::from_secret(secret.as_bytes()),
+            DecodingKey::from_secret(secret.as_bytes()),
+            None,
+        )
+    }
+
+    // without a kid one is derived from the public key
+    pub fn from_pem(pem: &[u8], kid: Option<String>) -> anyhow::Result<Self> {
+        let parsed = pem::parse(pem)?;
+        let der = parsed.contents();
+
+        let rsa = match parsed.tag() {
+            "RSA PRIVATE KEY" => {
+                Some(RsaKeyPair::from_der(der).map_err(|e| anyhow!("invalid rsa key: {}", e))?)
+            }
+            "PRIVATE KEY" => RsaKeyPair::from_pkcs8(der).ok(),
+            tag => bail!("unsupported PEM block {}, expected a private key", tag),
+        };
+
+        if let Some(key_pair) = rsa {
+            let public = PublicKeyComponents::<Vec<u8>>::from(key_pair.public());
+            let kid = kid.unwrap_or_else(|| derive_kid(&[public.n.as_slice(), &public.e].concat()));
+            let (n, e) = (
+                URL_SAFE_NO_PAD.encode(&public.n),
+                URL_SAFE_NO_PAD.encode(&public.e),
+            );
+            let jwk =
+                json!({"kty": "RSA", "use": "sig", "alg": "RS256", "kid": kid, "n": n, "e": e});
+
+            return Ok(Self::new(
+                kid,
+                Algorithm::RS256,
+                EncodingKey::from_rsa_pem(pem)?,
+                DecodingKey::from_rsa_components(&n, &e)?,
+                Some(jwk),
+            ));
+        }
+
+        let key_pair = Ed25519KeyPair::from_pkcs8_maybe_unchecked(der)
+            .map_err(|_| anyhow!("private key is neither RSA nor Ed25519"))?;
+        let public = key_pair.public_key().as_ref();
+        let kid = kid.unwrap_or_else(|| derive_kid(public));
+        let x = URL_SAFE_NO_PAD.encode(public);
+        let jwk = json!({"kty": "OKP", "crv": "Ed25519", "use": "sig", "alg": "EdDSA", "kid": kid, "x": x});
+
+        Ok(Self::new(
+            kid,
+            Algorithm::EdDSA,
+            EncodingKey::from_ed_pem(pem)?,
+            DecodingKey::from_ed_components(&x)?,
+            Some(jwk),
+        ))
+    }
+
+    fn new(
+        kid: String,
+        algorithm: Algorithm,
+        encoding_key: EncodingKey,
+        decoding_key: DecodingKey,
+        jwk: Option<Value>,
+    ) -> Self {
+        Self {
+            kid,
+            algorithm,
+            encoding_key,
+            decoding_key,
+            validation: Validation::new(algorithm),
+            jwk,
+        }
+    }
+}
+
+fn derive_kid(key: &[u8]) -> String {
+    hex::encode(&Sha256::digest(key)[..8])
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Utc};
+    use jsonwebtoken::Header;
+    use uuid::Uuid;
+
+    use super::{JwtInstance, JwtKey};
+    use crate::auth::{Role, User};
+
+    const RSA_PKCS1: &[u8] = include_bytes!("../testdata/rsa_pkcs1.pem");
+    const RSA_PKCS8: &[u8] = include_bytes!("../testdata/rsa_pkcs8.pem");
+    const ED25519: &[u8] = include_bytes!("../testdata/ed25519.pem");
+
+    fn user() -> User {
+        User {
+            sub: "Alice".to_string(),
+            email: "alice@example.com".to_string(),
+            dn: "Sponsormanager".to_string(),
+            exp: (Utc::now() + Duration::minutes(5)).timestamp() as usize,
+            role: Role::USER,
+            sid: Uuid::nil(),
+            token: None,
+        }
+    }
+
+    fn key(pem: &[u8]) -> JwtKey {
+        JwtKey::from_pem(pem, None).unwrap()
+    }
+
+    fn round_trip(pem: &[u8], alg: &str, kty: &str) {
+        let jwt = JwtInstance::new(key(pem), vec![]);
+
+        let token = jwt.create_jwt(&user()).unwrap();
+        assert_eq!(jwt.validate_jwt(&token).unwrap().email, "alice@example.com");
+        let jwk = &jwt.jwks()["keys"][0];
+        assert_eq!(jwk["alg"], alg);
+        assert_eq!(jwk["kty"], kty);
+    }
+
+    #[test]
+    fn signs_with_pkcs1_rsa_key() {
+        round_trip(RSA_PKCS1, "RS256", "RSA");
+    }
+
+    #[test]
+    fn signs_with_pkcs8_rsa_key() {
+        round_trip(RSA_PKCS8, "RS256", "RSA");
+    }
+
+    #[test]
+    fn signs_with_ed25519_key() {
+        round_trip(ED25519, "EdDSA", "OKP");
+    }
+
+    #[test]
+    fn rejects_non_private_keys() {
+        let pem = b"-----BEGIN PUBLIC KEY-----\nAAAA\n-----END PUBLIC KEY-----\n";
+        assert!(JwtKey::from_pem(pem, None).is_err());
+    }
+
+    #[test]
+    fn accepts_tokens_of_previous_keys() {
+        let old = JwtInstance::new(key(ED25519), vec![]);
+        let token = old.create_jwt(&user()).unwrap();
+
+        let rotated = JwtInstance::new(key(RSA_PKCS1), vec![key(ED25519)]);
+        assert!(rotated.validate_jwt(&token).is_ok());
+        assert_eq!(rotated.jwks()["keys"].as_array().unwrap().len(), 2);
+
+        let forgotten = JwtInstance::new(key(RSA_PKCS1), vec![]);
+        assert!(forgotten.validate_jwt(&token).is_err());
+    }
+
+    #[test]
+    fn accepts_tokens_without_kid() {
+        let jwt = JwtInstance::new(JwtKey::from_secret("new"), vec![JwtKey::from_secret("old")]);
+        let old = JwtKey::from_secret("old");
+        let token =
+            jsonwebtoken::encode(&Header::new(old.algorithm), &user(), &old.encoding_key).unwrap();
+
+        assert!(jwt.validate_jwt(&token).is_ok());
+    }
+
+    #[test]
+    fn rejects_tokens_with_unknown_kid() {
+        let jwt = JwtInstance::new(JwtKey::from_secret("secret"), vec![]);
+        let key = JwtKey::from_secret("secret");
+        let mut header = Header::new(key.algorithm);
+        header.kid = Some("unknown".to_string());
+        let token = jsonwebtoken::encode(&header, &user(), &key.encoding_key).unwrap();
+
+        assert!(jwt.validate_jwt(&token).is_err());
+    }
+}
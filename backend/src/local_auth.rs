@@ -0,0 +1,143 @@
+use std::sync::OnceLock;
+
+use anyhow::bail;
+use argon2::password_hash::rand_core::OsRng;
+use argon2::password_hash::SaltString;
+use argon2::{Argon2, PasswordHash, PasswordHasher, PasswordVerifier};
+use chrono::Utc;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::auth::User;
+use crate::misc::normalize_email;
+use crate::models::mongo::LocalAccount;
+use crate::queries::store::SponsorStore;
+use crate::session::random_token;
+use crate::AppStateStruct;
+
+pub const MIN_PASSWORD_LENGTH: usize = 10;
+
+// verified for unknown emails, so the response time does not reveal which exist
+static DUMMY_HASH: OnceLock<String> = OnceLock::new();
+
+pub async fn login(
+    state: &AppStateStruct,
+    email: &str,
+    password: &str,
+) -> anyhow::Result<Option<User>> {
+    let email = normalize_email(email);
+    let account = state.store.get_local_account(&email).await?;
+    let hash = match &account {
+        Some(account) => account.password_hash.clone(),
+        None => dummy_hash().await?,
+    };
+    let valid = verify_password(password.to_string(), hash).await?;
+    let Some(account) = account.filter(|_| valid) else {
+        return Ok(None);
+    };
+
+    let role = state.roles.resolve(state, &account.email).await?;
+
+    Ok(Some(User {
+        sub: account.name,
+        email: account.email,
+        dn: state.policy.organisation.clone(),
+        // both set by session::start_session
+        exp: 0,
+        role,
+        sid: Uuid::nil(),
+        token: None,
+    }))
+}
+
+pub async fn set_account(
+    store: &dyn SponsorStore,
+    email: &str,
+    name: &str,
+    password: &str,
+) -> anyhow::Result<LocalAccount> {
+    let email = normalize_email(email);
+    let created = match store.get_local_account(&email).await? {
+        Some(existing) => existing.created,
+        None => Utc::now(),
+    };
+    let account = LocalAccount {
+        email,
+        name: name.trim().to_string(),
+        password_hash: hash_password(password.to_string()).await?,
+        created,
+    };
+    store.upsert_local_account(&account).await?;
+
+    Ok(account)
+}
+
+// only on a fresh install, deleted accounts stay deleted
+pub async fn seed_local_accounts(
+    store: &dyn SponsorStore,
+    emails: &[String],
+    password: Option<&str>,
+) -> anyhow::Result<()> {
+    // computed now rather than during the first login with an unknown email
+    dummy_hash().await?;
+
+    if !store.get_local_accounts().await?.is_empty() {
+        return Ok(());
+    }
+    let emails: Vec<String> = emails
+        .iter()
+        .map(|e| normalize_email(e))
+        .filter(|e| !e.is_empty())
+        .collect();
+    if emails.is_empty() {
+        warn!("No local accounts exist, set BOOTSTRAP_ADMINS and BOOTSTRAP_PASSWORD to create one");
+        return Ok(());
+    }
+
+    let Some(password) = password.filter(|p| !p.is_empty()) else {
+        bail!("BOOTSTRAP_PASSWORD is required to create the accounts of BOOTSTRAP_ADMINS");
+    };
+    if password.len() < MIN_PASSWORD_LENGTH {
+        bail!(
+            "BOOTSTRAP_PASSWORD must be at least {} characters",
+            MIN_PASSWORD_LENGTH
+        );
+    }
+    for email in emails {
+        set_account(store, &email, &email, password).await?;
+        info!("Created local account {}", email);
+    }
+
+    Ok(())
+}
+
+async fn hash_password(password: String) -> anyhow::Result<String> {
+    tokio::task::spawn_blocking(move || {
+        let salt = SaltString::generate(&mut OsRng);
+        Ok(Argon2::default()
+            .hash_password(password.as_bytes(), &salt)
+            .map_err(|e| anyhow::anyhow!("hashing password failed: {}", e))?
+            .to_string())
+    })
+    .await?
+}
+
+async fn dummy_hash() -> anyhow::Result<String> {
+    if let Some(hash) = DUMMY_HASH.get() {
+        return Ok(hash.clone());
+    }
+    let hash = hash_password(random_token()).await?;
+    Ok(DUMMY_HASH.get_or_init(|| hash).clone())
+}
+
+async fn verify_password(password: String, hash: String) -> anyhow::Result<bool> {
+    Ok(tokio::task::spawn_blocking(move || {
+        let Ok(hash) = PasswordHash::new(&hash) else {
+            return false;
+        };
+        Argon2::default()
+            .verify_password(password.as_bytes(), &hash)
+            .is_ok()
+    })
+    .await?)
+}
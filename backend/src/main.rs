@@ -1,7 +1,9 @@
 use std::process::exit;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
+use anyhow::Context;
 use axum::extract::DefaultBodyLimit;
+use axum::http::HeaderValue;
 use axum::response::Response;
 use axum::routing::{get, get_service, post};
 use axum::Router;
@@ -11,22 +13,34 @@ use tower_http::cors::CorsLayer;
 use tower_http::services::{ServeDir, ServeFile};
 use tower_http::trace::TraceLayer;
 use tracing::level_filters::LevelFilter;
-use tracing::{error, info};
+use tracing::{error, info, warn};
 use tracing_subscriber::layer::SubscriberExt;
 use tracing_subscriber::util::SubscriberInitExt;
 
-use crate::auth::{JwtInstance, OpenIdInstance};
+use crate::auth::{
+    seed_bootstrap_admins, AccessPolicy, AuthProvider, GroupRoles, OpenIdInstance, Role, RoleCache,
+};
 use crate::error::AppError;
+use crate::jwt::{JwtInstance, JwtKey};
+use crate::meili_sync::SyncStatus;
+use crate::queries::embedded::EmbeddedIndex;
+use crate::queries::index::SearchIndex;
 use crate::queries::meili::MeiliQueries;
+use crate::queries::memory::MemoryQueries;
 use crate::queries::mongo::MongoQueries;
+use crate::queries::store::SponsorStore;
 
+pub mod api_token;
 pub mod auth;
 pub mod error;
+pub mod jwt;
+pub mod local_auth;
 mod meili_sync;
 pub mod misc;
 pub mod models;
 pub mod queries;
 mod routes;
+pub mod session;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
@@ -43,59 +57,87 @@ async fn main() -> anyhow::Result<()> {
         .init();
 
     info!("Fetching config...");
-    let config = match envy::from_env::<Config>() {
+    let mut config = match envy::from_env::<Config>() {
         Ok(c) => c,
         Err(e) => {
             error!("error fetching config: {:?}", e);
             exit(-1);
         }
     };
+    config.drop_empty_entries();
+
+    if config.auth_provider == AuthProvider::Oidc
+        && config.allowed_email_domains.is_empty()
+        && config.allowed_emails.is_empty()
+    {
+        warn!(
+            "Neither ALLOWED_EMAIL_DOMAINS nor ALLOWED_EMAILS set, nobody will be able to log in"
+        );
+    }
 
     info!("Initializing state...");
+    let store: Box<dyn SponsorStore> = match &config.mongo_url {
+        Some(mongo_url) => Box::new(MongoQueries::new(mongo_url).await?),
+        None => {
+            warn!("MONGO_URL not set, using in-memory store. All data is lost on restart!");
+            Box::new(MemoryQueries::new())
+        }
+    };
+
+    seed_bootstrap_admins(store.as_ref(), &config.bootstrap_admins).await?;
+
+    let index: Box<dyn SearchIndex> = match &config.meili_uri {
+        Some(meili_uri) => Box::new(MeiliQueries::new(meili_uri, config.meili_token.as_deref())),
+        None => {
+            warn!("MEILI_URI not set, using embedded search index");
+            Box::new(EmbeddedIndex::new())
+        }
+    };
+    if let Err(e) = index.setup().await {
+        warn!("search index setup failed, retrying on next sync: {:?}", e);
+    }
+
+    let oidc = match config.auth_provider {
+        AuthProvider::Oidc => Some(create_oidc(&config)?),
+        AuthProvider::Local => {
+            local_auth::seed_local_accounts(
+                store.as_ref(),
+                &config.bootstrap_admins,
+                config.bootstrap_password.as_deref(),
+            )
+            .await?;
+            None
+        }
+    };
+
     let state = Arc::new(AppStateStruct {
-        meili: MeiliQueries::new(&config.meili_uri, &config.meili_token).await?,
-        mongo: MongoQueries::new(&config.mongo_url).await?,
-        jwt: JwtInstance::new(&config.jwt_secret),
-        oidc: OpenIdInstance::new(
-            &config.oidc_client_id,
-            &config.oidc_client_secret,
-            &config.oidc_issuer_url,
-            &config.oidc_redirect_url,
-        )
-        .await?,
+        index,
+        store,
+        sync_status: Mutex::new(SyncStatus::default()),
+        policy: AccessPolicy {
+            allowed_domains: config.allowed_email_domains.clone(),
+            allowed_emails: config.allowed_emails.clone(),
+            organisation: config.organisation_name.clone(),
+            default_role: config.default_role,
+            owners_only_edit: config.owners_only_edit,
+        },
+        roles: RoleCache::new(),
+        jwt: create_jwt(&config)?,
+        oidc,
         config,
     });
 
-    let app = Router::new()
-        .route("/", get(|| async { "Hello World. " }))
-        .route("/health", get(routes::healthcheck))
-        .route("/create", post(routes::create))
-        .route("/search", get(routes::search))
-        .route("/delete", post(routes::delete))
-        .route("/whoami", get(routes::whoami))
-        .route("/get/:sponsor_uid", get(routes::get))
-        .route("/get_all", get(routes::get_all))
-        .route("/get_logo/:sponsor_uid", get(routes::get_logo))
-        .route("/update", post(routes::update))
-        .route("/upload_logo", post(routes::upload_logo))
-        .route("/settings/get", get(routes::settings::get))
-        .route("/settings/update", post(routes::settings::update))
-        .route("/settings/admins", get(routes::settings::get_admins))
-        .route(
-            "/settings/admins/update",
-            post(routes::settings::update_admins),
-        )
-        .route("/login", get(routes::login))
-        .route("/login/code", get(routes::login_code))
-        .route("/changes/:offset", get(routes::changes))
-        .layer(DefaultBodyLimit::max(16 * 1024 * 1024));
-
     let static_files_service = get_service(
         ServeDir::new("dist/")
             .append_index_html_on_directories(true)
             .fallback(ServeFile::new("dist/index.html")),
     );
 
+    let frontend_origin = openidconnect::url::Url::parse(&state.config.frontend_url)?
+        .origin()
+        .ascii_serialization();
+    let frontend_origin = HeaderValue::from_str(&frontend_origin)?;
+
     info!("Starting meili sync...");
     meili_sync::sync_meili(state.clone());
 
@@ -103,12 +145,14 @@ async fn main() -> anyhow::Result<()> {
         .serve(
             Router::new()
                 .fallback(static_files_service)
-                .nest("/api", app)
+                .nest("/api", router())
                 .layer(
+                    // cookies are only sent along if the origin is named explicitly
                     CorsLayer::new()
-                        .allow_origin(cors::Any)
-                        .allow_headers(cors::Any)
-                        .allow_methods(cors::Any),
+                        .allow_origin(frontend_origin)
+                        .allow_headers(cors::AllowHeaders::mirror_request())
+                        .allow_methods(cors::AllowMethods::mirror_request())
+                        .allow_credentials(true),
                 )
                 .layer(TraceLayer::new_for_http())
                 .with_state(state)
@@ -119,27 +163,884 @@ async fn main() -> anyhow::Result<()> {
     Ok(())
 }
 
+fn create_jwt(config: &Config) -> anyhow::Result<JwtInstance> {
+    let secret = config.jwt_secret.as_deref().filter(|s| !s.is_empty());
+
+    let mut previous_keys = Vec::new();
+    for entry in &config.jwt_previous_key_files {
+        // `kid=path` for keys that were used with JWT_KEY_ID
+        let (kid, file) = match entry.split_once('=') {
+            Some((kid, file)) => (Some(kid.trim().to_string()), file.trim()),
+            None => (None, entry.trim()),
+        };
+        let pem = std::fs::read(file).with_context(|| format!("reading {}", file))?;
+        previous_keys
+            .push(JwtKey::from_pem(&pem, kid).with_context(|| format!("parsing {}", file))?);
+    }
+    previous_keys.extend(
+        config
+            .jwt_previous_secrets
+            .iter()
+            .map(|s| JwtKey::from_secret(s)),
+    );
+
+    let signing_key = match config
+        .jwt_private_key_file
+        .as_deref()
+        .filter(|f| !f.is_empty())
+    {
+        Some(file) => {
+            // tokens signed with the secret stay valid after switching to a private key
+            previous_keys.extend(secret.map(JwtKey::from_secret));
+            let pem = std::fs::read(file).with_context(|| format!("reading {}", file))?;
+            let kid = config.jwt_key_id.clone().filter(|k| !k.is_empty());
+            JwtKey::from_pem(&pem, kid).with_context(|| format!("parsing {}", file))?
+        }
+        None => match secret {
+            Some(secret) => JwtKey::from_secret(secret),
+            None => anyhow::bail!("either JWT_SECRET or JWT_PRIVATE_KEY_FILE is required"),
+        },
+    };
+
+    Ok(JwtInstance::new(signing_key, previous_keys))
+}
+
+fn create_oidc(config: &Config) -> anyhow::Result<OpenIdInstance> {
+    let (Some(client_id), Some(client_secret), Some(issuer_url), Some(redirect_url)) = (
+        &config.oidc_client_id,
+        &config.oidc_client_secret,
+        &config.oidc_issuer_url,
+        &config.oidc_redirect_url,
+    ) else {
+        anyhow::bail!(
+            "OIDC_CLIENT_ID, OIDC_CLIENT_SECRET, OIDC_ISSUER_URL and OIDC_REDIRECT_URL are required unless AUTH_PROVIDER=local"
+        );
+    };
+
+    let group_roles = match config.oidc_groups_claim.as_deref().map(str::trim) {
+        Some(claim) if !claim.is_empty() => Some(GroupRoles::parse(
+            claim.to_string(),
+            &config.oidc_group_roles,
+        )?),
+        _ => None,
+    };
+
+    OpenIdInstance::new(
+        client_id,
+        client_secret,
+        issuer_url,
+        redirect_url,
+        config.oidc_extra_scopes.clone(),
+        group_roles,
+        config.oidc_require_pkce,
+    )
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/", get(|| async { "Hello World. " }))
+        .route("/health", get(routes::healthcheck))
+        .route("/jwks", get(routes::jwks))
+        .route("/create", post(routes::create))
+        .route("/search", get(routes::search))
+        .route("/delete", post(routes::delete))
+        .route("/whoami", get(routes::whoami))
+        .route("/get/:sponsor_uid", get(routes::get))
+        .route("/get_all", get(routes::get_all))
+        .route("/my_sponsors", get(routes::my_sponsors))
+        .route("/get_logo/:sponsor_uid", get(routes::get_logo))
+        .route("/update", post(routes::update))
+        .route("/upload_logo", post(routes::upload_logo))
+        .route("/tick_favour", post(routes::tick_favour))
+        .route("/settings/get", get(routes::settings::get))
+        .route("/settings/update", post(routes::settings::update))
+        .route("/settings/admins", get(routes::settings::get_admins))
+        .route(
+            "/settings/admins/update",
+            post(routes::settings::update_admins),
+        )
+        .route("/settings/roles", get(routes::settings::get_roles))
+        .route(
+            "/settings/roles/update",
+            post(routes::settings::update_roles),
+        )
+        .route("/settings/accounts", get(routes::settings::get_accounts))
+        .route(
+            "/settings/accounts/update",
+            post(routes::settings::update_account),
+        )
+        .route(
+            "/settings/accounts/delete",
+            post(routes::settings::delete_account),
+        )
+        .route(
+            "/settings/search/status",
+            get(routes::settings::search_status),
+        )
+        .route("/settings/search/reindex", post(routes::settings::reindex))
+        .route("/login", get(routes::login).post(routes::login_local))
+        .route("/login/provider", get(routes::login_provider))
+        .route("/password", post(routes::change_password))
+        .route("/login/code", get(routes::login_code))
+        .route("/refresh", post(routes::refresh))
+        .route("/logout", post(routes::logout))
+        .route(
+            "/settings/sessions/revoke",
+            post(routes::settings::revoke_sessions),
+        )
+        .route("/tokens", get(routes::tokens::list))
+        .route("/tokens/create", post(routes::tokens::create))
+        .route("/tokens/revoke", post(routes::tokens::revoke))
+        .route("/changes/:offset", get(routes::changes))
+        .layer(DefaultBodyLimit::max(16 * 1024 * 1024))
+}
+
 pub type AppResult = Result<Response, AppError>;
 pub type AppState = Arc<AppStateStruct>;
 
 pub struct AppStateStruct {
-    meili: MeiliQueries,
-    mongo: MongoQueries,
+    index: Box<dyn SearchIndex>,
+    store: Box<dyn SponsorStore>,
+    sync_status: Mutex<SyncStatus>,
+    policy: AccessPolicy,
+    roles: RoleCache,
     jwt: JwtInstance,
-    oidc: OpenIdInstance,
+    oidc: Option<OpenIdInstance>,
     config: Config,
 }
 
 #[derive(Deserialize, Debug)]
 struct Config {
-    meili_uri: String,
-    meili_token: String,
-    mongo_url: String,
-    jwt_secret: String,
+    meili_uri: Option<String>,
+    meili_token: Option<String>,
+    mongo_url: Option<String>,
+    jwt_secret: Option<String>,
+    #[serde(default)]
+    jwt_previous_secrets: Vec<String>,
+    jwt_private_key_file: Option<String>,
+    jwt_key_id: Option<String>,
+    // comma separated, kid=path for keys that had a JWT_KEY_ID
+    #[serde(default)]
+    jwt_previous_key_files: Vec<String>,
     frontend_url: String,
 
-    oidc_redirect_url: String,
-    oidc_client_id: String,
-    oidc_client_secret: String,
-    oidc_issuer_url: String,
+    // comma separated, * allows every domain
+    #[serde(default)]
+    allowed_email_domains: Vec<String>,
+    #[serde(default)]
+    allowed_emails: Vec<String>,
+    #[serde(default = "default_organisation_name")]
+    organisation_name: String,
+    #[serde(default = "default_role")]
+    default_role: Role,
+    // sponsors created before owners existed need owners assigned by an admin
+    #[serde(default)]
+    owners_only_edit: bool,
+    // only used on a fresh install
+    #[serde(default)]
+    bootstrap_admins: Vec<String>,
+    bootstrap_password: Option<String>,
+
+    #[serde(default)]
+    auth_provider: AuthProvider,
+
+    oidc_redirect_url: Option<String>,
+    oidc_client_id: Option<String>,
+    oidc_client_secret: Option<String>,
+    oidc_issuer_url: Option<String>,
+    #[serde(default)]
+    oidc_extra_scopes: Vec<String>,
+    oidc_groups_claim: Option<String>,
+    // comma separated group=ROLE pairs, the highest role wins
+    #[serde(default)]
+    oidc_group_roles: Vec<String>,
+    #[serde(default)]
+    oidc_require_pkce: bool,
+}
+
+impl Config {
+    // compose passes unset lists as empty strings, which envy parses to [""]
+    fn drop_empty_entries(&mut self) {
+        for list in [
+            &mut self.allowed_email_domains,
+            &mut self.allowed_emails,
+            &mut self.bootstrap_admins,
+            &mut self.jwt_previous_secrets,
+            &mut self.jwt_previous_key_files,
+            &mut self.oidc_extra_scopes,
+            &mut self.oidc_group_roles,
+        ] {
+            list.retain(|entry| !entry.trim().is_empty());
+        }
+    }
+}
+
+fn default_role() -> Role {
+    Role::USER
+}
+
+fn default_organisation_name() -> String {
+    "Sponsormanager".to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use axum::body::Body;
+    use axum::http::{header, Method, Request, StatusCode};
+    use serde_json::{json, Value};
+    use tower::ServiceExt;
+    use uuid::Uuid;
+
+    use crate::auth::{AccessPolicy, Role, RoleCache, User};
+    use crate::jwt::{JwtInstance, JwtKey};
+    use crate::meili_sync::SyncStatus;
+    use crate::models::mongo::UserRole;
+    use crate::queries::embedded::EmbeddedIndex;
+    use crate::queries::memory::MemoryQueries;
+    use crate::{
+        auth, create_jwt, local_auth, meili_sync, router, session, AppState, AppStateStruct, Config,
+    };
+
+    fn memory_state() -> AppState {
+        memory_state_with(false)
+    }
+
+    fn memory_state_with(owners_only_edit: bool) -> AppState {
+        let config: Config = envy::from_iter([
+            ("JWT_SECRET".to_string(), "secret".to_string()),
+            (
+                "FRONTEND_URL".to_string(),
+                "http://localhost:3000".to_string(),
+            ),
+            ("AUTH_PROVIDER".to_string(), "local".to_string()),
+        ])
+        .unwrap();
+
+        Arc::new(AppStateStruct {
+            index: Box::new(EmbeddedIndex::new()),
+            store: Box::new(MemoryQueries::new()),
+            sync_status: Mutex::new(SyncStatus::default()),
+            policy: AccessPolicy {
+                allowed_domains: vec![],
+                allowed_emails: vec![],
+                organisation: config.organisation_name.clone(),
+                default_role: Role::USER,
+                owners_only_edit,
+            },
+            roles: RoleCache::new(),
+            jwt: JwtInstance::new(JwtKey::from_secret("secret"), vec![]),
+            oidc: None,
+            config,
+        })
+    }
+
+    fn user(email: &str) -> User {
+        User {
+            sub: email.to_string(),
+            email: email.to_string(),
+            dn: email.to_string(),
+            exp: 0,
+            role: Role::USER,
+            sid: Uuid::nil(),
+            token: None,
+        }
+    }
+
+    async fn login(state: &AppState, email: &str) -> String {
+        session::start_session(state, user(email))
+            .await
+            .unwrap()
+            .access_token
+    }
+
+    fn sponsor() -> Value {
+        json!({
+            "name": "Acme",
+            "shortDescription": "Rockets and anvils",
+            "fields": [],
+            "tags": [],
+            "favours": [{"condition": "Logo on the car", "completed": false, "dueUntil": "2030-01-01T00:00:00Z"}],
+        })
+    }
+
+    async fn call(
+        state: &AppState,
+        method: Method,
+        uri: &str,
+        token: &str,
+        body: Option<Value>,
+    ) -> (StatusCode, Value) {
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(header::AUTHORIZATION, format!("Bearer {}", token))
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(body.map_or_else(Body::empty, |b| Body::from(b.to_string())))
+            .unwrap();
+
+        let response = router()
+            .with_state(state.clone())
+            .oneshot(request)
+            .await
+            .unwrap();
+        let status = response.status();
+        let bytes = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        (
+            status,
+            serde_json::from_slice(&bytes).unwrap_or(Value::Null),
+        )
+    }
+
+    #[tokio::test]
+    async fn create_then_search() {
+        let state = memory_state();
+        let token = login(&state, "editor@example.com").await;
+
+        let (status, created) =
+            call(&state, Method::POST, "/create", &token, Some(sponsor())).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(created["owners"], json!(["editor@example.com"]));
+
+        // indexed by the dirty queue, not by the request
+        let (_, found) = call(
+            &state,
+            Method::GET,
+            "/search?search=anvils&type=sponsors",
+            &token,
+            None,
+        )
+        .await;
+        assert_eq!(found["results"], json!([]));
+        meili_sync::run_dirty(&state).await.unwrap();
+        let (status, found) = call(
+            &state,
+            Method::GET,
+            "/search?search=anvils&type=sponsors",
+            &token,
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(found["results"][0]["uid"], created["uid"]);
+
+        let (status, _) = call(
+            &state,
+            Method::GET,
+            "/search?search=anvils&type=sponsors",
+            "invalid",
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        let (status, _) = call(
+            &state,
+            Method::POST,
+            "/delete",
+            &token,
+            Some(json!({"uid": created["uid"]})),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        meili_sync::run_dirty(&state).await.unwrap();
+        let (_, found) = call(
+            &state,
+            Method::GET,
+            "/search?search=anvils&type=sponsors",
+            &token,
+            None,
+        )
+        .await;
+        assert_eq!(found["results"], json!([]));
+    }
+
+    #[tokio::test]
+    async fn search_favours_completed_in_range() {
+        let state = memory_state();
+        let token = login(&state, "editor@example.com").await;
+
+        let (_, created) = call(&state, Method::POST, "/create", &token, Some(sponsor())).await;
+        let favour = &created["favours"][0];
+        assert_eq!(favour["completedAt"], Value::Null);
+
+        let before = chrono::Utc::now() - chrono::Duration::seconds(1);
+        let tick = json!({"sponsorUid": created["uid"], "uid": favour["uid"], "completed": true});
+        let (status, ticked) = call(&state, Method::POST, "/tick_favour", &token, Some(tick)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(ticked["favours"][0]["completedAt"].is_string());
+
+        // the tick is only picked up by the dirty sync
+        meili_sync::run(&state).await.unwrap();
+
+        let search = |param: &str| {
+            format!(
+                "/search?search=&type=favours&{}={}",
+                param,
+                before.format("%Y-%m-%dT%H:%M:%SZ")
+            )
+        };
+        let (status, found) = call(
+            &state,
+            Method::GET,
+            &search("completed_after"),
+            &token,
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(found["results"][0]["uid"], favour["uid"]);
+
+        let (_, found) = call(
+            &state,
+            Method::GET,
+            &search("completed_before"),
+            &token,
+            None,
+        )
+        .await;
+        assert_eq!(found["results"], json!([]));
+
+        let (status, _) = call(
+            &state,
+            Method::GET,
+            &(search("completed_after") + "&completed=false"),
+            &token,
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn search_rejects_overflowing_offset() {
+        let state = memory_state();
+        let token = login(&state, "editor@example.com").await;
+
+        let uri = format!(
+            "/search?search=&type=sponsors&offset={}&limit=20",
+            usize::MAX
+        );
+        let (status, _) = call(&state, Method::GET, &uri, &token, None).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn refresh_token_is_redeemed_once() {
+        let state = memory_state();
+        let tokens = session::start_session(&state, user("editor@example.com"))
+            .await
+            .unwrap();
+
+        let (first, second) = tokio::join!(
+            session::refresh_session(&state, &tokens.refresh_token),
+            session::refresh_session(&state, &tokens.refresh_token),
+        );
+        let rotated = [first.unwrap(), second.unwrap()]
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>();
+        assert_eq!(rotated.len(), 1);
+
+        assert!(session::refresh_session(&state, &rotated[0].refresh_token)
+            .await
+            .unwrap()
+            .is_some());
+        assert!(session::refresh_session(&state, &tokens.refresh_token)
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn expired_sessions_are_not_refreshed() {
+        let state = memory_state();
+        let tokens = session::start_session(&state, user("editor@example.com"))
+            .await
+            .unwrap();
+        let sid = tokens
+            .refresh_token
+            .split_once('.')
+            .unwrap()
+            .0
+            .parse::<Uuid>()
+            .unwrap();
+
+        let mut expired = state.store.get_session(&sid.into()).await.unwrap().unwrap();
+        expired.expires = chrono::Utc::now() - chrono::Duration::seconds(1);
+        state.store.create_session(&expired).await.unwrap();
+
+        assert!(session::refresh_session(&state, &tokens.refresh_token)
+            .await
+            .unwrap()
+            .is_none());
+        let session = state.store.get_session(&sid.into()).await.unwrap().unwrap();
+        assert_eq!(session.refresh_token_hash, expired.refresh_token_hash);
+    }
+
+    #[tokio::test]
+    async fn csrf_token_from_login_body_authorizes_cookie_requests() {
+        let state = memory_state();
+        local_auth::set_account(
+            state.store.as_ref(),
+            "editor@example.com",
+            "Editor",
+            "correct horse battery",
+        )
+        .await
+        .unwrap();
+
+        let login = Request::post("/login")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(
+                json!({"email": "editor@example.com", "password": "correct horse battery"})
+                    .to_string(),
+            ))
+            .unwrap();
+        let response = router()
+            .with_state(state.clone())
+            .oneshot(login)
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let cookies = response
+            .headers()
+            .get_all(header::SET_COOKIE)
+            .iter()
+            .map(|c| c.to_str().unwrap().split(';').next().unwrap().to_string())
+            .collect::<Vec<_>>()
+            .join("; ");
+        let body: Value =
+            serde_json::from_slice(&hyper::body::to_bytes(response.into_body()).await.unwrap())
+                .unwrap();
+        let csrf_token = body["csrfToken"].as_str().unwrap();
+
+        let logout = |csrf: Option<&str>| {
+            let mut request = Request::post("/logout").header(header::COOKIE, &cookies);
+            if let Some(csrf) = csrf {
+                request = request.header("X-CSRF-Token", csrf);
+            }
+            request.body(Body::empty()).unwrap()
+        };
+        let response = router()
+            .with_state(state.clone())
+            .oneshot(logout(None))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let response = router()
+            .with_state(state.clone())
+            .oneshot(logout(Some(csrf_token)))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn api_tokens_are_bounded_and_die_with_their_account() {
+        let state = memory_state();
+        local_auth::set_account(
+            state.store.as_ref(),
+            "editor@example.com",
+            "Editor",
+            "correct horse battery",
+        )
+        .await
+        .unwrap();
+        let session = login(&state, "editor@example.com").await;
+
+        let too_long = json!({"name": "ci", "scope": "READ", "expiresInDays": u32::MAX});
+        let (status, _) = call(
+            &state,
+            Method::POST,
+            "/tokens/create",
+            &session,
+            Some(too_long),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let valid = json!({"name": "ci", "scope": "READ", "expiresInDays": 30});
+        let (status, created) = call(
+            &state,
+            Method::POST,
+            "/tokens/create",
+            &session,
+            Some(valid),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let token = created["token"].as_str().unwrap();
+
+        let (status, _) = call(&state, Method::GET, "/whoami", token, None).await;
+        assert_eq!(status, StatusCode::OK);
+
+        state
+            .store
+            .delete_local_account("editor@example.com")
+            .await
+            .unwrap();
+        let (status, _) = call(&state, Method::GET, "/whoami", token, None).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn non_owners_tick_favours_but_cannot_edit() {
+        let state = memory_state_with(true);
+        let owner = login(&state, "owner@example.com").await;
+        let other = login(&state, "other@example.com").await;
+
+        let (_, mut created) = call(&state, Method::POST, "/create", &owner, Some(sponsor())).await;
+
+        let tick = json!({"sponsorUid": created["uid"], "uid": created["favours"][0]["uid"], "completed": true});
+        let (status, _) = call(&state, Method::POST, "/tick_favour", &other, Some(tick)).await;
+        assert_eq!(status, StatusCode::OK);
+
+        created["name"] = json!("Renamed");
+        let (status, _) = call(&state, Method::POST, "/update", &other, Some(created)).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn deleting_an_account_removes_its_tokens_and_role() {
+        let state = memory_state();
+        let admin = UserRole {
+            email: "admin@example.com".to_string(),
+            role: Role::ADMIN,
+        };
+        state.store.add_or_update_role(&admin).await.unwrap();
+        let editor = UserRole {
+            email: "editor@example.com".to_string(),
+            role: Role::FAVOUR_MANAGER,
+        };
+        state.store.add_or_update_role(&editor).await.unwrap();
+        local_auth::set_account(
+            state.store.as_ref(),
+            "editor@example.com",
+            "Editor",
+            "correct horse battery",
+        )
+        .await
+        .unwrap();
+        let session = login(&state, "editor@example.com").await;
+        let valid = json!({"name": "ci", "scope": "READ", "expiresInDays": 30});
+        let (status, _) = call(
+            &state,
+            Method::POST,
+            "/tokens/create",
+            &session,
+            Some(valid),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+
+        let admin = login(&state, "admin@example.com").await;
+        let body = json!({"email": "Editor@Example.com"});
+        let (status, _) = call(
+            &state,
+            Method::POST,
+            "/settings/accounts/delete",
+            &admin,
+            Some(body),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+
+        assert!(state
+            .store
+            .get_api_tokens("editor@example.com")
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            state
+                .store
+                .get_user_role("editor@example.com")
+                .await
+                .unwrap(),
+            None
+        );
+        let (status, _) = call(&state, Method::GET, "/whoami", &session, None).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn demoted_bootstrap_admins_stay_demoted_after_restart() {
+        let state = memory_state();
+        let bootstrap_admins = vec![
+            "first@example.com".to_string(),
+            "second@example.com".to_string(),
+        ];
+        auth::seed_bootstrap_admins(state.store.as_ref(), &bootstrap_admins)
+            .await
+            .unwrap();
+
+        let admin = login(&state, "first@example.com").await;
+        let roles = json!({"roles": [{"email": "first@example.com", "role": "ADMIN"}]});
+        let (status, _) = call(
+            &state,
+            Method::POST,
+            "/settings/roles/update",
+            &admin,
+            Some(roles),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            state
+                .store
+                .get_user_role("second@example.com")
+                .await
+                .unwrap(),
+            None
+        );
+
+        auth::seed_bootstrap_admins(state.store.as_ref(), &bootstrap_admins)
+            .await
+            .unwrap();
+        assert_eq!(
+            state
+                .store
+                .get_user_role("second@example.com")
+                .await
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            state
+                .store
+                .get_user_role("first@example.com")
+                .await
+                .unwrap(),
+            Some(Role::ADMIN)
+        );
+    }
+
+    #[test]
+    fn previous_key_files_keep_their_key_id() {
+        let config = |vars: &[(&str, &str)]| -> Config {
+            let mut vars: Vec<(String, String)> = vars
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect();
+            vars.push((
+                "FRONTEND_URL".to_string(),
+                "http://localhost:3000".to_string(),
+            ));
+            envy::from_iter(vars).unwrap()
+        };
+        let ed25519 = concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/ed25519.pem");
+        let rsa = concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/rsa_pkcs8.pem");
+
+        let old = create_jwt(&config(&[
+            ("JWT_PRIVATE_KEY_FILE", ed25519),
+            ("JWT_KEY_ID", "custom"),
+        ]))
+        .unwrap();
+        let mut alice = user("alice@example.com");
+        alice.exp = (chrono::Utc::now() + chrono::Duration::minutes(5)).timestamp() as usize;
+        let token = old.create_jwt(&alice).unwrap();
+
+        let previous = format!("custom={}", ed25519);
+        let rotated = create_jwt(&config(&[
+            ("JWT_PRIVATE_KEY_FILE", rsa),
+            ("JWT_PREVIOUS_KEY_FILES", &previous),
+        ]))
+        .unwrap();
+        assert_eq!(
+            rotated.validate_jwt(&token).unwrap().email,
+            "alice@example.com"
+        );
+        assert_eq!(rotated.jwks()["keys"][1]["kid"], "custom");
+    }
+
+    #[tokio::test]
+    async fn revoking_sessions_ignores_email_case() {
+        let state = memory_state();
+        let admin = UserRole {
+            email: "admin@example.com".to_string(),
+            role: Role::ADMIN,
+        };
+        state.store.add_or_update_role(&admin).await.unwrap();
+        let admin = login(&state, "admin@example.com").await;
+        let session = login(&state, "alice@example.com").await;
+
+        let body = json!({"email": " Alice@Example.com"});
+        let (status, revoked) = call(
+            &state,
+            Method::POST,
+            "/settings/sessions/revoke",
+            &admin,
+            Some(body),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(revoked["revoked"], 1);
+
+        let (status, _) = call(&state, Method::GET, "/whoami", &session, None).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn update_rejects_empty_owners() {
+        let state = memory_state_with(true);
+        let owner = login(&state, "owner@example.com").await;
+        let (_, mut created) = call(&state, Method::POST, "/create", &owner, Some(sponsor())).await;
+
+        created["owners"] = json!([" "]);
+        let (status, _) = call(
+            &state,
+            Method::POST,
+            "/update",
+            &owner,
+            Some(created.clone()),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        created.as_object_mut().unwrap().remove("owners");
+        let (status, updated) = call(&state, Method::POST, "/update", &owner, Some(created)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(updated["owners"], json!(["owner@example.com"]));
+    }
+
+    #[tokio::test]
+    async fn bootstrap_accounts_use_the_configured_password_once() {
+        let state = memory_state();
+        let store = state.store.as_ref();
+        let emails = vec![
+            "first@example.com".to_string(),
+            "second@example.com".to_string(),
+        ];
+
+        assert!(local_auth::seed_local_accounts(store, &emails, None)
+            .await
+            .is_err());
+        assert!(
+            local_auth::seed_local_accounts(store, &emails, Some("short"))
+                .await
+                .is_err()
+        );
+        local_auth::seed_local_accounts(store, &emails, Some("correct horse battery"))
+            .await
+            .unwrap();
+        let user = local_auth::login(&state, "First@Example.com", "correct horse battery")
+            .await
+            .unwrap();
+        assert_eq!(user.unwrap().email, "first@example.com");
+
+        store
+            .delete_local_account("second@example.com")
+            .await
+            .unwrap();
+        local_auth::seed_local_accounts(store, &emails, Some("correct horse battery"))
+            .await
+            .unwrap();
+        assert!(store
+            .get_local_account("second@example.com")
+            .await
+            .unwrap()
+            .is_none());
+    }
 }
@@ -1,16 +1,37 @@
+use std::collections::HashSet;
 use std::panic::AssertUnwindSafe;
 use std::process::exit;
 use std::sync::Once;
 
+use anyhow::anyhow;
+use chrono::{DateTime, Utc};
 use futures::FutureExt;
-use tracing::{error, info};
+use mongodb::bson;
+use serde::Serialize;
+use tracing::{debug, error, info};
+use uuid::Uuid;
 
-use crate::AppState;
 use crate::models::meili::MeiliSponsorFavour;
 use crate::models::mongo::Sponsor;
+use crate::queries::index::SearchIndex;
+use crate::AppState;
 
 static ALREADY_STARTED: Once = Once::new();
 
+const DIRTY_INTERVAL_SECS: u64 = 5;
+
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct SyncStatus {
+    pub running: bool,
+    #[serde(rename = "startedAt")]
+    pub started_at: Option<DateTime<Utc>>,
+    #[serde(rename = "finishedAt")]
+    pub finished_at: Option<DateTime<Utc>>,
+    pub deleted: Option<usize>,
+    pub inserted: Option<usize>,
+    pub error: Option<String>,
+}
+
 pub fn sync_meili(state: AppState) {
     if ALREADY_STARTED.is_completed() {
         return;
@@ -19,12 +40,18 @@ pub fn sync_meili(state: AppState) {
     ALREADY_STARTED.call_once(|| {});
     tokio::spawn(async move {
         let future = AssertUnwindSafe(async move {
-            let mut interval = tokio::time::interval(tokio::time::Duration::from_secs(600));
+            // catch up on everything that happened while we were down
+            if let Err(e) = run(&state).await {
+                error!("Failed to sync meili: {:?}", e);
+            }
+
+            let mut interval =
+                tokio::time::interval(tokio::time::Duration::from_secs(DIRTY_INTERVAL_SECS));
             loop {
                 interval.tick().await;
 
-                if let Err(e) = run(&state).await {
-                    error!("Failed to sync meili: {:?}", e);
+                if let Err(e) = run_dirty(&state).await {
+                    error!("Failed to sync dirty sponsors to meili: {:?}", e);
                 }
             }
         });
@@ -36,52 +63,448 @@ pub fn sync_meili(state: AppState) {
     });
 }
 
-async fn run(state: &AppState) -> anyhow::Result<()> {
+pub async fn run(state: &AppState) -> anyhow::Result<()> {
+    if !begin_run(state) {
+        return Err(anyhow!("full meili sync already running"));
+    }
+
+    let result = reconcile(state).await;
+    finish_run(state, &result);
+
+    result.map(|_| ())
+}
+
+pub fn trigger_run(state: AppState) -> bool {
+    if !begin_run(&state) {
+        return false;
+    }
+
+    tokio::spawn(async move {
+        let result = reconcile(&state).await;
+        finish_run(&state, &result);
+
+        if let Err(e) = result {
+            error!("Failed to sync meili: {:?}", e);
+        }
+    });
+
+    true
+}
+
+fn begin_run(state: &AppState) -> bool {
+    let mut status = state.sync_status.lock().unwrap();
+    if status.running {
+        return false;
+    }
+
+    *status = SyncStatus {
+        running: true,
+        started_at: Some(Utc::now()),
+        ..Default::default()
+    };
+    true
+}
+
+fn finish_run(state: &AppState, result: &anyhow::Result<(usize, usize)>) {
+    let mut status = state.sync_status.lock().unwrap();
+    status.running = false;
+    status.finished_at = Some(Utc::now());
+
+    match result {
+        Ok((deleted, inserted)) => {
+            status.deleted = Some(*deleted);
+            status.inserted = Some(*inserted);
+        }
+        Err(e) => status.error = Some(e.to_string()),
+    }
+}
+
+async fn reconcile(state: &AppState) -> anyhow::Result<(usize, usize)> {
     info!("Syncing meili...");
 
-    let mongo_sponsors = state.mongo.get_all().await?;
+    state.index.setup().await?;
+
+    let mongo_sponsors = state.store.get_all().await?;
 
-    let deleted = delete_dangling_meili(state, &mongo_sponsors).await?;
+    let deleted = delete_dangling_meili(state.index.as_ref(), &mongo_sponsors).await?;
     let inserted = insert_all_to_meili(state, &mongo_sponsors).await?;
 
-    info!("Suceessfully synced meili. Deleted: {}, Inserted: {}", deleted, inserted);
+    info!(
+        "Suceessfully synced meili. Deleted: {}, Inserted: {}",
+        deleted, inserted
+    );
 
-    Ok(())
+    Ok((deleted, inserted))
 }
 
-async fn delete_dangling_meili(state: &AppState, mongo_docs: &[Sponsor]) -> anyhow::Result<usize> {
-    let mut deleted = 0;
-    for meili in state.meili.get_all_sponsors().await? {
-        if mongo_docs.iter().any(|s| s.uid == meili.id.into()) {
-            continue;
+pub async fn run_dirty(state: &AppState) -> anyhow::Result<()> {
+    let dirty = state.store.take_dirty().await?;
+    if dirty.is_empty() {
+        return Ok(());
+    }
+
+    debug!("Syncing {} dirty sponsors to meili...", dirty.len());
+    for (i, uid) in dirty.iter().enumerate() {
+        if let Err(e) = sync_sponsor(state, *uid).await {
+            // put back everything not synced yet so the next run retries it
+            for uid in &dirty[i..] {
+                state.store.mark_dirty(uid).await?;
+            }
+            return Err(e);
         }
+    }
 
-        state.meili.delete_sponsor(&meili.id).await?;
-        deleted += 1;
+    Ok(())
+}
+
+pub async fn sync_sponsor(state: &AppState, uid: bson::Uuid) -> anyhow::Result<()> {
+    let meili_uid: Uuid = uid.into();
+    let indexed_favours = state.index.get_favour_ids_of_sponsor(&meili_uid).await?;
+
+    let Some(sponsor) = state.store.get(uid).await? else {
+        state.index.delete_sponsor(&meili_uid).await?;
+        state.index.delete_favours(&indexed_favours).await?;
+        return Ok(());
+    };
+
+    let current_favours = sponsor
+        .favours
+        .iter()
+        .map(|f| f.uid.into())
+        .collect::<HashSet<Uuid>>();
+    let removed_favours = indexed_favours
+        .into_iter()
+        .filter(|f| !current_favours.contains(f))
+        .collect::<Vec<_>>();
+
+    state.index.insert_sponsor(&sponsor.clone().into()).await?;
+    state
+        .index
+        .insert_favours(&MeiliSponsorFavour::from_sponsor_vec(&sponsor.favours))
+        .await?;
+    if !removed_favours.is_empty() {
+        state.index.delete_favours(&removed_favours).await?;
     }
 
-    for meili in state.meili.get_all_favours().await? {
-        if mongo_docs.iter().any(|s| s.favours.iter().any(|f| f.uid == meili.id.into())) {
+    Ok(())
+}
+
+async fn delete_dangling_meili(
+    index: &dyn SearchIndex,
+    mongo_docs: &[Sponsor],
+) -> anyhow::Result<usize> {
+    let sponsor_uids = mongo_docs
+        .iter()
+        .map(|s| s.uid.into())
+        .collect::<HashSet<Uuid>>();
+    let favour_uids = mongo_docs
+        .iter()
+        .flat_map(|s| s.favours.iter().map(|f| f.uid.into()))
+        .collect::<HashSet<Uuid>>();
+
+    // list everything before deleting, deleting while paging would shift the offsets
+    let mut deleted = 0;
+    for id in index.get_all_sponsor_ids().await? {
+        if sponsor_uids.contains(&id) {
             continue;
         }
 
-        state.meili.delete_favours(&[meili.id]).await?;
+        index.delete_sponsor(&id).await?;
+        deleted += 1;
     }
 
+    let dangling_favours = index
+        .get_all_favour_ids()
+        .await?
+        .into_iter()
+        .filter(|id| !favour_uids.contains(id))
+        .collect::<Vec<_>>();
+    if !dangling_favours.is_empty() {
+        index.delete_favours(&dangling_favours).await?;
+    }
 
     Ok(deleted)
 }
 
-pub async fn insert_all_to_meili(state: &AppState, mongo_docs: &Vec<Sponsor>) -> anyhow::Result<usize> {
+pub async fn insert_all_to_meili(
+    state: &AppState,
+    mongo_docs: &Vec<Sponsor>,
+) -> anyhow::Result<usize> {
     let mut inserted = 0;
     for sponsor in mongo_docs {
-        state.meili.insert_sponsor(&sponsor.clone().into()).await?;
+        state.index.insert_sponsor(&sponsor.clone().into()).await?;
 
-        state.meili.insert_favours(
-            &MeiliSponsorFavour::from_sponsor_vec(&sponsor.favours)
-        ).await?;
+        state
+            .index
+            .insert_favours(&MeiliSponsorFavour::from_sponsor_vec(&sponsor.favours))
+            .await?;
         inserted += 1;
     }
 
     Ok(inserted)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashMap, HashSet};
+    use std::net::SocketAddr;
+    use std::sync::{Arc, Mutex};
+
+    use async_trait::async_trait;
+    use axum::extract::{Path, Query, State};
+    use axum::http::StatusCode;
+    use axum::routing::{delete, get, post};
+    use axum::{Json, Router};
+    use serde_json::{json, Value};
+    use uuid::Uuid;
+
+    use crate::models::meili::{
+        FavourSearch, FavourSearchResult, MeiliSponsor, MeiliSponsorFavour, SponsorSearch,
+        SponsorSearchResult,
+    };
+    use crate::models::mongo::{Sponsor, SponsorFavour};
+    use crate::queries::embedded::EmbeddedIndex;
+    use crate::queries::index::SearchIndex;
+    use crate::queries::meili::MeiliQueries;
+
+    use super::delete_dangling_meili;
+
+    // mimics meilisearch ignoring the requested limit
+    struct CappedIndex(EmbeddedIndex);
+
+    const CAPPED_PAGE_SIZE: usize = 20;
+
+    #[async_trait]
+    impl SearchIndex for CappedIndex {
+        async fn insert_sponsor(&self, sponsor: &MeiliSponsor) -> anyhow::Result<()> {
+            self.0.insert_sponsor(sponsor).await
+        }
+
+        async fn insert_favours(&self, favours: &[MeiliSponsorFavour]) -> anyhow::Result<()> {
+            self.0.insert_favours(favours).await
+        }
+
+        async fn get_sponsors(
+            &self,
+            search: &SponsorSearch,
+        ) -> anyhow::Result<SponsorSearchResult> {
+            self.0.get_sponsors(search).await
+        }
+
+        async fn get_favours(&self, search: &FavourSearch) -> anyhow::Result<FavourSearchResult> {
+            self.0.get_favours(search).await
+        }
+
+        async fn get_favour_ids_of_sponsor(&self, sponsor_uid: &Uuid) -> anyhow::Result<Vec<Uuid>> {
+            self.0.get_favour_ids_of_sponsor(sponsor_uid).await
+        }
+
+        async fn delete_sponsor(&self, uid: &Uuid) -> anyhow::Result<()> {
+            self.0.delete_sponsor(uid).await
+        }
+
+        async fn delete_favours(&self, uid: &[Uuid]) -> anyhow::Result<()> {
+            self.0.delete_favours(uid).await
+        }
+
+        async fn get_sponsor_ids_page(
+            &self,
+            offset: usize,
+            limit: usize,
+        ) -> anyhow::Result<Vec<Uuid>> {
+            self.0
+                .get_sponsor_ids_page(offset, limit.min(CAPPED_PAGE_SIZE))
+                .await
+        }
+
+        async fn get_favour_ids_page(
+            &self,
+            offset: usize,
+            limit: usize,
+        ) -> anyhow::Result<Vec<Uuid>> {
+            self.0
+                .get_favour_ids_page(offset, limit.min(CAPPED_PAGE_SIZE))
+                .await
+        }
+    }
+
+    fn sponsor(favours: usize) -> Sponsor {
+        let uid = Uuid::new_v4().into();
+        Sponsor {
+            uid,
+            name: "Sponsor".to_string(),
+            short_description: "description".to_string(),
+            image_url: None,
+            fields: Vec::new(),
+            tags: HashSet::new(),
+            favours: (0..favours)
+                .map(|_| SponsorFavour {
+                    uid: Uuid::new_v4().into(),
+                    sponsor_uid: uid,
+                    condition: "condition".to_string(),
+                    completed: false,
+                    due_until: chrono::Utc::now(),
+                    completed_at: None,
+                })
+                .collect(),
+            owners: HashSet::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn deletes_dangling_documents_beyond_first_page() {
+        let index = CappedIndex(EmbeddedIndex::new());
+        let sponsors = (0..50).map(|_| sponsor(2)).collect::<Vec<_>>();
+        for sponsor in sponsors.iter() {
+            index.insert_sponsor(&sponsor.clone().into()).await.unwrap();
+            index
+                .insert_favours(&MeiliSponsorFavour::from_sponsor_vec(&sponsor.favours))
+                .await
+                .unwrap();
+        }
+
+        let (kept, removed) = sponsors.split_at(5);
+        let deleted = delete_dangling_meili(&index, kept).await.unwrap();
+
+        assert_eq!(deleted, removed.len());
+
+        let sponsor_ids = index
+            .get_all_sponsor_ids()
+            .await
+            .unwrap()
+            .into_iter()
+            .collect::<HashSet<_>>();
+        let expected = kept.iter().map(|s| s.uid.into()).collect::<HashSet<Uuid>>();
+        assert_eq!(sponsor_ids, expected);
+
+        let favour_ids = index
+            .get_all_favour_ids()
+            .await
+            .unwrap()
+            .into_iter()
+            .collect::<HashSet<_>>();
+        let expected = kept
+            .iter()
+            .flat_map(|s| s.favours.iter().map(|f| f.uid.into()))
+            .collect::<HashSet<Uuid>>();
+        assert_eq!(favour_ids, expected);
+    }
+
+    #[tokio::test]
+    async fn lists_all_ids_across_short_pages() {
+        let index = CappedIndex(EmbeddedIndex::new());
+        for sponsor in (0..45).map(|_| sponsor(0)) {
+            index.insert_sponsor(&sponsor.into()).await.unwrap();
+        }
+
+        assert_eq!(index.get_all_sponsor_ids().await.unwrap().len(), 45);
+    }
+
+    type StubDocuments = Arc<Mutex<HashMap<String, Vec<Uuid>>>>;
+
+    // meilisearch's document routes, 20 documents per page unless a limit is given
+    async fn meili_stub(documents: StubDocuments) -> SocketAddr {
+        async fn list(
+            State(documents): State<StubDocuments>,
+            Path(index): Path<String>,
+            Query(query): Query<HashMap<String, String>>,
+        ) -> Json<Value> {
+            let offset = query.get("offset").map_or(0, |o| o.parse().unwrap());
+            let limit = query.get("limit").map_or(20, |l| l.parse().unwrap());
+            let ids = documents
+                .lock()
+                .unwrap()
+                .get(&index)
+                .cloned()
+                .unwrap_or_default();
+            let results: Vec<Value> = ids
+                .iter()
+                .skip(offset)
+                .take(limit)
+                .map(|id| json!({"id": id}))
+                .collect();
+            Json(json!({"results": results, "offset": offset, "limit": limit, "total": ids.len()}))
+        }
+
+        fn task(index: &str) -> (StatusCode, Json<Value>) {
+            let task = json!({
+                "taskUid": 0,
+                "indexUid": index,
+                "status": "enqueued",
+                "type": "documentDeletion",
+                "enqueuedAt": "2024-01-01T00:00:00Z",
+            });
+            (StatusCode::ACCEPTED, Json(task))
+        }
+
+        async fn delete_one(
+            State(documents): State<StubDocuments>,
+            Path((index, id)): Path<(String, Uuid)>,
+        ) -> (StatusCode, Json<Value>) {
+            documents
+                .lock()
+                .unwrap()
+                .entry(index.clone())
+                .or_default()
+                .retain(|d| *d != id);
+            task(&index)
+        }
+
+        async fn delete_batch(
+            State(documents): State<StubDocuments>,
+            Path(index): Path<String>,
+            Json(ids): Json<Vec<Uuid>>,
+        ) -> (StatusCode, Json<Value>) {
+            documents
+                .lock()
+                .unwrap()
+                .entry(index.clone())
+                .or_default()
+                .retain(|d| !ids.contains(d));
+            task(&index)
+        }
+
+        let router = Router::new()
+            .route("/indexes/:index/documents", get(list))
+            .route("/indexes/:index/documents/delete-batch", post(delete_batch))
+            .route("/indexes/:index/documents/:id", delete(delete_one))
+            .with_state(documents);
+        let server =
+            axum::Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(router.into_make_service());
+        let addr = server.local_addr();
+        tokio::spawn(server);
+        addr
+    }
+
+    #[tokio::test]
+    async fn meili_deletes_dangling_documents_beyond_default_page() {
+        let sponsors = (0..50).map(|_| sponsor(2)).collect::<Vec<_>>();
+        let documents = StubDocuments::default();
+        documents.lock().unwrap().insert(
+            "sponsors".to_string(),
+            sponsors.iter().map(|s| s.uid.into()).collect(),
+        );
+        documents.lock().unwrap().insert(
+            "favours".to_string(),
+            sponsors
+                .iter()
+                .flat_map(|s| s.favours.iter().map(|f| f.uid.into()))
+                .collect(),
+        );
+        let addr = meili_stub(documents.clone()).await;
+        let index = MeiliQueries::new(&format!("http://{}", addr), None);
+
+        let (kept, removed) = sponsors.split_at(5);
+        let deleted = delete_dangling_meili(&index, kept).await.unwrap();
+
+        assert_eq!(deleted, removed.len());
+        let documents = documents.lock().unwrap();
+        let expected = kept.iter().map(|s| s.uid.into()).collect::<Vec<Uuid>>();
+        assert_eq!(documents["sponsors"], expected);
+        let expected = kept
+            .iter()
+            .flat_map(|s| s.favours.iter().map(|f| f.uid.into()))
+            .collect::<Vec<Uuid>>();
+        assert_eq!(documents["favours"], expected);
+    }
+}
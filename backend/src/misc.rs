@@ -1,3 +1,5 @@
+use std::collections::HashSet;
+
 use anyhow::anyhow;
 
 use crate::models::mongo::Sponsor;
@@ -17,6 +19,9 @@ pub fn assert_sponsor(sponsor: &Sponsor) -> anyhow::Result<()> {
     for favour in sponsor.favours.iter() {
         return_err!(favour.condition.is_empty(), "favour empty");
     }
+    for owner in sponsor.owners.iter() {
+        return_err!(!owner.contains('@'), "owner {} is not an email", owner);
+    }
     for field in sponsor.fields.iter() {
         return_err!(field.name.is_empty(), "field name empty");
         return_err!(field.value.is_empty(), "field {} value empty", &field.name);
@@ -24,3 +29,45 @@ pub fn assert_sponsor(sponsor: &Sponsor) -> anyhow::Result<()> {
 
     Ok(())
 }
+
+pub fn normalize_email(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+pub fn normalize_emails(emails: HashSet<String>) -> HashSet<String> {
+    emails
+        .iter()
+        .map(|e| normalize_email(e))
+        .filter(|e| !e.is_empty())
+        .collect()
+}
+
+// browsers treat //host and /\host as another host
+pub fn is_same_origin_path(path: &str) -> bool {
+    path.starts_with('/')
+        && !path.starts_with("//")
+        && !path.contains('\\')
+        && !path.chars().any(char::is_control)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::is_same_origin_path;
+
+    #[test]
+    fn accepts_paths_on_the_same_origin() {
+        assert!(is_same_origin_path("/"));
+        assert!(is_same_origin_path("/ok?x=1"));
+        assert!(is_same_origin_path("/sponsors/1#favours"));
+    }
+
+    #[test]
+    fn rejects_paths_leaving_the_origin() {
+        assert!(!is_same_origin_path("//evil"));
+        assert!(!is_same_origin_path("/\\evil"));
+        assert!(!is_same_origin_path("https://x"));
+        assert!(!is_same_origin_path(""));
+        assert!(!is_same_origin_path("evil"));
+        assert!(!is_same_origin_path("/\tevil"));
+    }
+}
@@ -1,12 +1,17 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 
-use chrono::Utc;
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
 use uuid::Uuid;
 
 use crate::models::mongo::{Sponsor, SponsorFavour, SponsorField};
 
-#[derive(Serialize, Deserialize, Debug)]
+pub const DEFAULT_PAGE_SIZE: usize = 20;
+pub const MAX_TOTAL_HITS: usize = 1000;
+pub const HIGHLIGHTED_ATTRIBUTES: [&str; 4] = ["name", "shortDescription", "tags", "fields"];
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct MeiliSponsor {
     pub id: Uuid,
     pub name: String,
@@ -14,15 +19,22 @@ pub struct MeiliSponsor {
     pub short_description: String,
     pub tags: HashSet<String>,
     pub fields: Vec<MeiliSponsorField>,
+    // name:value, so a filter can require both on the same field
+    #[serde(rename = "fieldPairs", default)]
+    pub field_pairs: Vec<String>,
+    #[serde(rename = "hasOpenFavours", default)]
+    pub has_open_favours: bool,
+    #[serde(default)]
+    pub owners: HashSet<String>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct MeiliSponsorField {
     pub name: String,
     pub value: String,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct MeiliSponsorFavour {
     pub id: Uuid,
     pub sponsor_uid: Uuid,
@@ -30,16 +42,137 @@ pub struct MeiliSponsorFavour {
     pub completed: bool,
     #[serde(rename = "dueUntil")]
     pub due_until: chrono::DateTime<Utc>,
+    // meilisearch can only filter and sort numbers
+    #[serde(rename = "dueUntilTimestamp", default)]
+    pub due_until_timestamp: i64,
+    #[serde(rename = "completedAt", default)]
+    pub completed_at: Option<chrono::DateTime<Utc>>,
+    #[serde(rename = "completedAtTimestamp", default)]
+    pub completed_at_timestamp: Option<i64>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SponsorSearch {
+    pub query: String,
+    pub tags: Vec<String>,
+    pub field_name: Option<String>,
+    pub field_value: Option<String>,
+    pub has_open_favours: Option<bool>,
+    pub owner: Option<String>,
+    pub page: Page,
+    pub highlight: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SponsorSearchResult {
+    pub hits: Vec<SponsorHit>,
+    pub tag_facets: HashMap<String, usize>,
+    pub estimated_total_hits: usize,
+}
+
+impl SponsorSearch {
+    pub fn matches(&self, sponsor: &MeiliSponsor) -> bool {
+        if !self.tags.iter().all(|t| sponsor.tags.contains(t)) {
+            return false;
+        }
+
+        let field_matches = sponsor.fields.iter().any(|f| {
+            self.field_name.as_ref().is_none_or(|n| &f.name == n)
+                && self.field_value.as_ref().is_none_or(|v| &f.value == v)
+        });
+        if (self.field_name.is_some() || self.field_value.is_some()) && !field_matches {
+            return false;
+        }
+
+        self.has_open_favours
+            .is_none_or(|open| sponsor.has_open_favours == open)
+            && self
+                .owner
+                .as_ref()
+                .is_none_or(|o| sponsor.owners.contains(o))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SponsorHit {
+    pub sponsor: MeiliSponsor,
+    pub formatted: Option<Map<String, Value>>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct FavourSearch {
+    pub query: String,
+    pub completed: Option<bool>,
+    pub due_before: Option<DateTime<Utc>>,
+    pub due_after: Option<DateTime<Utc>>,
+    pub completed_before: Option<DateTime<Utc>>,
+    pub completed_after: Option<DateTime<Utc>>,
+    pub overdue: bool,
+    pub sort: Option<DueSort>,
+    pub page: Page,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct FavourSearchResult {
+    pub hits: Vec<MeiliSponsorFavour>,
+    pub estimated_total_hits: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Page {
+    pub offset: usize,
+    pub limit: usize,
+}
+
+impl Default for Page {
+    fn default() -> Self {
+        Self {
+            offset: 0,
+            limit: DEFAULT_PAGE_SIZE,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DueSort {
+    Asc,
+    Desc,
+}
+
+impl FavourSearch {
+    pub fn matches(&self, favour: &MeiliSponsorFavour, now: DateTime<Utc>) -> bool {
+        self.completed.is_none_or(|c| favour.completed == c)
+            && self.due_before.is_none_or(|d| favour.due_until < d)
+            && self.due_after.is_none_or(|d| favour.due_until >= d)
+            && self
+                .completed_before
+                .is_none_or(|d| favour.completed_at.is_some_and(|c| c < d))
+            && self
+                .completed_after
+                .is_none_or(|d| favour.completed_at.is_some_and(|c| c >= d))
+            && (!self.overdue || (!favour.completed && favour.due_until < now))
+    }
+}
+
+impl MeiliSponsorField {
+    pub fn pair(&self) -> String {
+        format!("{}:{}", self.name, self.value)
+    }
 }
 
 impl From<Sponsor> for MeiliSponsor {
     fn from(value: Sponsor) -> Self {
+        let fields: Vec<MeiliSponsorField> = value.fields.into_iter().map(|x| x.into()).collect();
+
         MeiliSponsor {
             id: value.uid.into(),
             name: value.name,
             short_description: value.short_description,
             tags: value.tags,
-            fields: value.fields.into_iter().map(|x| x.into()).collect(),
+            field_pairs: fields.iter().map(MeiliSponsorField::pair).collect(),
+            fields,
+            has_open_favours: value.favours.iter().any(|f| !f.completed),
+            owners: value.owners,
         }
     }
 }
@@ -61,14 +194,16 @@ impl From<SponsorFavour> for MeiliSponsorFavour {
             sponsor_uid: value.sponsor_uid.into(),
             completed: value.completed,
             due_until: value.due_until,
+            due_until_timestamp: value.due_until.timestamp(),
+            completed_at: value.completed_at,
+            completed_at_timestamp: value.completed_at.map(|c| c.timestamp()),
         }
     }
 }
 
 impl MeiliSponsorFavour {
     pub fn from_sponsor_vec(vec: &[SponsorFavour]) -> Vec<MeiliSponsorFavour> {
-        vec
-            .iter()
+        vec.iter()
             .map(|favour| MeiliSponsorFavour::from(favour.clone()))
             .collect()
     }
@@ -1,3 +1,3 @@
-pub mod mongo;
 pub mod meili;
+pub mod mongo;
 pub mod rest;
@@ -2,9 +2,10 @@ use std::collections::HashSet;
 
 use chrono::Utc;
 use mongodb::bson;
+use mongodb::bson::oid::ObjectId;
 use serde::{Deserialize, Serialize};
 
-use crate::auth::Role;
+use crate::auth::{Role, GROUP_ROLE_MAX_AGE_HOURS};
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Sponsor {
@@ -16,6 +17,8 @@ pub struct Sponsor {
     pub fields: Vec<SponsorField>,
     pub tags: HashSet<String>,
     pub favours: Vec<SponsorFavour>,
+    #[serde(default)]
+    pub owners: HashSet<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -32,6 +35,22 @@ pub struct SponsorFavour {
     pub condition: String,
     pub completed: bool,
     pub due_until: chrono::DateTime<Utc>,
+    // None for favours completed before this was recorded
+    #[serde(default)]
+    pub completed_at: Option<chrono::DateTime<Utc>>,
+}
+
+impl SponsorFavour {
+    pub fn completed_at(
+        completed: bool,
+        previous: Option<&SponsorFavour>,
+    ) -> Option<chrono::DateTime<Utc>> {
+        match previous {
+            _ if !completed => None,
+            Some(previous) if previous.completed => previous.completed_at,
+            _ => Some(Utc::now()),
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Default)]
@@ -55,6 +74,32 @@ pub enum ChangeType {
     ChangedSettings(Settings),
     ChangeLogo(Sponsor),
     ChangeUserRole(UserRole),
+    RevokeSessions(String),
+    ChangeLocalAccount(String),
+    DeleteLocalAccount(String),
+}
+
+impl ChangeType {
+    pub fn sponsor_uid(&self) -> Option<bson::Uuid> {
+        match self {
+            ChangeType::AddSponsor(s)
+            | ChangeType::DeleteSponsor(s)
+            | ChangeType::ChangeSponsor(s)
+            | ChangeType::ChangeLogo(s) => Some(s.uid),
+            ChangeType::ChangedSettings(_)
+            | ChangeType::ChangeUserRole(_)
+            | ChangeType::RevokeSessions(_)
+            | ChangeType::ChangeLocalAccount(_)
+            | ChangeType::DeleteLocalAccount(_) => None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DirtySponsor {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub sponsor_uid: bson::Uuid,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
@@ -63,6 +108,77 @@ pub struct UserRole {
     pub role: Role,
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GroupRole {
+    pub email: String,
+    pub role: Role,
+    // missing in old entries, which then count as stale
+    #[serde(default)]
+    pub updated: chrono::DateTime<Utc>,
+}
+
+impl GroupRole {
+    pub fn is_fresh(&self) -> bool {
+        self.updated + chrono::Duration::hours(GROUP_ROLE_MAX_AGE_HOURS) > Utc::now()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Session {
+    #[serde(rename = "_id")]
+    pub uid: bson::Uuid,
+    pub email: String,
+    pub sub: String,
+    pub dn: String,
+    pub refresh_token_hash: String,
+    pub created: chrono::DateTime<Utc>,
+    // bson date for the TTL index
+    #[serde(with = "bson::serde_helpers::chrono_datetime_as_bson_datetime")]
+    pub expires: chrono::DateTime<Utc>,
+    pub revoked: bool,
+}
+
+impl Session {
+    pub fn is_active(&self) -> bool {
+        !self.revoked && self.expires > Utc::now()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ApiToken {
+    #[serde(rename = "_id")]
+    pub uid: bson::Uuid,
+    pub email: String,
+    pub sub: String,
+    pub name: String,
+    pub scope: TokenScope,
+    pub token_hash: String,
+    pub created: chrono::DateTime<Utc>,
+    pub expires: Option<chrono::DateTime<Utc>>,
+}
+
+impl ApiToken {
+    pub fn is_active(&self) -> bool {
+        self.expires.is_none_or(|e| e > Utc::now())
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenScope {
+    READ,
+    WRITE,
+    ADMIN,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LocalAccount {
+    #[serde(rename = "_id")]
+    pub email: String,
+    pub name: String,
+    pub password_hash: String,
+    pub created: chrono::DateTime<Utc>,
+}
+
 impl Change {
     pub fn new(who: impl Into<String>, what: ChangeType) -> Self {
         Self {
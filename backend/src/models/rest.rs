@@ -1,11 +1,17 @@
 use std::collections::HashSet;
 
-use chrono::Utc;
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
 use uuid::Uuid;
 
-use crate::models::meili::MeiliSponsorFavour;
-use crate::models::mongo::{Sponsor, SponsorFavour, SponsorField};
+use crate::error::AppError;
+use crate::misc::normalize_email;
+use crate::models::meili::{
+    DueSort, FavourSearch, MeiliSponsorFavour, Page, SponsorSearch, DEFAULT_PAGE_SIZE,
+    MAX_TOTAL_HITS,
+};
+use crate::models::mongo::{ApiToken, Sponsor, SponsorFavour, SponsorField, TokenScope};
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct RestSponsor {
@@ -20,6 +26,13 @@ pub struct RestSponsor {
     pub favours: Vec<RestSponsorFavour>,
     #[serde(rename = "favoursCompleted")]
     pub favours_completed: Option<bool>,
+    pub owners: Option<HashSet<String>>,
+    #[serde(
+        rename = "_formatted",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub formatted: Option<Map<String, Value>>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -37,6 +50,194 @@ pub struct RestSponsorFavour {
     pub completed: bool,
     #[serde(rename = "dueUntil")]
     pub due_until: chrono::DateTime<Utc>,
+    #[serde(rename = "completedAt", default)]
+    pub completed_at: Option<chrono::DateTime<Utc>>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct RestApiToken {
+    pub uid: Uuid,
+    pub name: String,
+    pub scope: TokenScope,
+    pub created: DateTime<Utc>,
+    pub expires: Option<DateTime<Utc>>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct SearchQuery {
+    pub search: String,
+    #[serde(rename = "type")]
+    pub kind: SearchKind,
+    #[serde(default)]
+    pub offset: usize,
+    #[serde(default = "default_limit")]
+    pub limit: usize,
+
+    pub tags: Option<String>,
+    pub field_name: Option<String>,
+    pub field_value: Option<String>,
+    pub open_favours: Option<bool>,
+    pub highlight: Option<bool>,
+    pub owner: Option<String>,
+
+    pub completed: Option<bool>,
+    pub overdue: Option<bool>,
+    pub due_before: Option<DateTime<Utc>>,
+    pub due_after: Option<DateTime<Utc>>,
+    pub completed_before: Option<DateTime<Utc>>,
+    pub completed_after: Option<DateTime<Utc>>,
+    pub sort: Option<FavourSort>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchKind {
+    Sponsors,
+    Favours,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FavourSort {
+    #[serde(rename = "dueUntil:asc")]
+    DueUntilAsc,
+    #[serde(rename = "dueUntil:desc")]
+    DueUntilDesc,
+}
+
+fn default_limit() -> usize {
+    DEFAULT_PAGE_SIZE
+}
+
+impl SearchQuery {
+    fn page(&self) -> Result<Page, AppError> {
+        if self.limit == 0 || self.limit > MAX_TOTAL_HITS {
+            return Err(AppError::new(
+                400,
+                format!("limit must be between 1 and {}", MAX_TOTAL_HITS),
+            ));
+        }
+        if self
+            .offset
+            .checked_add(self.limit)
+            .is_none_or(|end| end > MAX_TOTAL_HITS)
+        {
+            return Err(AppError::new(
+                400,
+                format!(
+                    "only the first {} hits can be paged through",
+                    MAX_TOTAL_HITS
+                ),
+            ));
+        }
+
+        Ok(Page {
+            offset: self.offset,
+            limit: self.limit,
+        })
+    }
+
+    fn reject_set(kind: &str, params: &[(&str, bool)]) -> Result<(), AppError> {
+        match params.iter().find(|(_, set)| *set) {
+            Some((name, _)) => Err(AppError::new(
+                400,
+                format!("{} cannot be used with type={}", name, kind),
+            )),
+            None => Ok(()),
+        }
+    }
+
+    pub fn into_sponsor_search(self) -> Result<SponsorSearch, AppError> {
+        Self::reject_set(
+            "sponsors",
+            &[
+                ("completed", self.completed.is_some()),
+                ("overdue", self.overdue.is_some()),
+                ("due_before", self.due_before.is_some()),
+                ("due_after", self.due_after.is_some()),
+                ("completed_before", self.completed_before.is_some()),
+                ("completed_after", self.completed_after.is_some()),
+                ("sort", self.sort.is_some()),
+            ],
+        )?;
+
+        Ok(SponsorSearch {
+            page: self.page()?,
+            query: self.search,
+            tags: self
+                .tags
+                .map(|t| {
+                    t.split(',')
+                        .filter(|t| !t.is_empty())
+                        .map(str::to_string)
+                        .collect()
+                })
+                .unwrap_or_default(),
+            field_name: self.field_name,
+            field_value: self.field_value,
+            has_open_favours: self.open_favours,
+            owner: self.owner.as_deref().map(normalize_email),
+            highlight: self.highlight.unwrap_or(false),
+        })
+    }
+
+    pub fn into_favour_search(self) -> Result<FavourSearch, AppError> {
+        Self::reject_set(
+            "favours",
+            &[
+                ("tags", self.tags.is_some()),
+                ("field_name", self.field_name.is_some()),
+                ("field_value", self.field_value.is_some()),
+                ("open_favours", self.open_favours.is_some()),
+                ("highlight", self.highlight.is_some()),
+                ("owner", self.owner.is_some()),
+            ],
+        )?;
+
+        let overdue = self.overdue.unwrap_or(false);
+        if overdue && self.completed == Some(true) {
+            return Err(AppError::new(400, "overdue favours cannot be completed"));
+        }
+        if let (Some(after), Some(before)) = (self.due_after, self.due_before) {
+            if after >= before {
+                return Err(AppError::new(400, "due_after must be before due_before"));
+            }
+        }
+
+        let mut completed = self.completed;
+        if self.completed_before.is_some() || self.completed_after.is_some() {
+            if overdue || completed == Some(false) {
+                return Err(AppError::new(
+                    400,
+                    "completed_before and completed_after only match completed favours",
+                ));
+            }
+            completed = Some(true);
+        }
+        if let (Some(after), Some(before)) = (self.completed_after, self.completed_before) {
+            if after >= before {
+                return Err(AppError::new(
+                    400,
+                    "completed_after must be before completed_before",
+                ));
+            }
+        }
+
+        Ok(FavourSearch {
+            page: self.page()?,
+            query: self.search,
+            completed,
+            due_before: self.due_before,
+            due_after: self.due_after,
+            completed_before: self.completed_before,
+            completed_after: self.completed_after,
+            overdue,
+            sort: self.sort.map(|s| match s {
+                FavourSort::DueUntilAsc => DueSort::Asc,
+                FavourSort::DueUntilDesc => DueSort::Desc,
+            }),
+        })
+    }
 }
 
 impl From<Sponsor> for RestSponsor {
@@ -52,6 +253,8 @@ impl From<Sponsor> for RestSponsor {
             tags: value.tags,
             favours: value.favours.into_iter().map(|f| f.into()).collect(),
             favours_completed,
+            owners: Some(value.owners),
+            formatted: None,
         }
     }
 }
@@ -73,6 +276,7 @@ impl From<SponsorFavour> for RestSponsorFavour {
             condition: value.condition,
             completed: value.completed,
             due_until: value.due_until,
+            completed_at: value.completed_at,
         }
     }
 }
@@ -85,6 +289,19 @@ impl From<MeiliSponsorFavour> for RestSponsorFavour {
             condition: value.condition,
             completed: value.completed,
             due_until: value.due_until,
+            completed_at: value.completed_at,
+        }
+    }
+}
+
+impl From<ApiToken> for RestApiToken {
+    fn from(token: ApiToken) -> Self {
+        Self {
+            uid: token.uid.into(),
+            name: token.name,
+            scope: token.scope,
+            created: token.created,
+            expires: token.expires,
         }
     }
 }
@@ -0,0 +1,301 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::RwLock;
+
+use async_trait::async_trait;
+use chrono::Utc;
+use serde_json::{json, Map, Value};
+use uuid::Uuid;
+
+use crate::models::meili::{
+    DueSort, FavourSearch, FavourSearchResult, MeiliSponsor, MeiliSponsorFavour, Page, SponsorHit,
+    SponsorSearch, SponsorSearchResult, MAX_TOTAL_HITS,
+};
+use crate::queries::index::SearchIndex;
+
+#[derive(Default)]
+pub struct EmbeddedIndex {
+    sponsors: RwLock<InvertedIndex<MeiliSponsor>>,
+    favours: RwLock<InvertedIndex<MeiliSponsorFavour>>,
+}
+
+impl EmbeddedIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl SearchIndex for EmbeddedIndex {
+    async fn insert_sponsor(&self, sponsor: &MeiliSponsor) -> anyhow::Result<()> {
+        self.sponsors.write().unwrap().insert(sponsor.clone());
+
+        Ok(())
+    }
+
+    async fn insert_favours(&self, favours: &[MeiliSponsorFavour]) -> anyhow::Result<()> {
+        let mut index = self.favours.write().unwrap();
+        for favour in favours {
+            index.insert(favour.clone());
+        }
+
+        Ok(())
+    }
+
+    async fn get_sponsors(&self, search: &SponsorSearch) -> anyhow::Result<SponsorSearchResult> {
+        let hits = self
+            .sponsors
+            .read()
+            .unwrap()
+            .search(&search.query)
+            .into_iter()
+            .filter(|s| search.matches(s))
+            .collect::<Vec<_>>();
+
+        let mut tag_facets = HashMap::new();
+        for tag in hits.iter().flat_map(|s| s.tags.iter()) {
+            *tag_facets.entry(tag.clone()).or_default() += 1;
+        }
+
+        let query = tokenize(&search.query);
+        Ok(SponsorSearchResult {
+            estimated_total_hits: hits.len(),
+            hits: page(hits, search.page)
+                .into_iter()
+                .map(|sponsor| SponsorHit {
+                    formatted: search.highlight.then(|| format_sponsor(&sponsor, &query)),
+                    sponsor,
+                })
+                .collect(),
+            tag_facets,
+        })
+    }
+
+    async fn get_favours(&self, search: &FavourSearch) -> anyhow::Result<FavourSearchResult> {
+        let now = Utc::now();
+        let mut hits = self
+            .favours
+            .read()
+            .unwrap()
+            .search(&search.query)
+            .into_iter()
+            .filter(|f| search.matches(f, now))
+            .collect::<Vec<_>>();
+
+        match search.sort {
+            Some(DueSort::Asc) => hits.sort_by_key(|f| f.due_until),
+            Some(DueSort::Desc) => hits.sort_by_key(|f| std::cmp::Reverse(f.due_until)),
+            None => {}
+        }
+
+        Ok(FavourSearchResult {
+            estimated_total_hits: hits.len(),
+            hits: page(hits, search.page),
+        })
+    }
+
+    async fn get_favour_ids_of_sponsor(&self, sponsor_uid: &Uuid) -> anyhow::Result<Vec<Uuid>> {
+        Ok(self
+            .favours
+            .read()
+            .unwrap()
+            .docs
+            .values()
+            .filter(|f| &f.sponsor_uid == sponsor_uid)
+            .map(|f| f.id)
+            .collect())
+    }
+
+    async fn delete_sponsor(&self, uid: &Uuid) -> anyhow::Result<()> {
+        self.sponsors.write().unwrap().remove(uid);
+
+        Ok(())
+    }
+
+    async fn delete_favours(&self, uid: &[Uuid]) -> anyhow::Result<()> {
+        let mut index = self.favours.write().unwrap();
+        for uid in uid {
+            index.remove(uid);
+        }
+
+        Ok(())
+    }
+
+    async fn get_sponsor_ids_page(&self, offset: usize, limit: usize) -> anyhow::Result<Vec<Uuid>> {
+        Ok(self.sponsors.read().unwrap().ids_page(offset, limit))
+    }
+
+    async fn get_favour_ids_page(&self, offset: usize, limit: usize) -> anyhow::Result<Vec<Uuid>> {
+        Ok(self.favours.read().unwrap().ids_page(offset, limit))
+    }
+}
+
+trait Document: Clone {
+    fn id(&self) -> Uuid;
+
+    fn text(&self) -> Vec<&str>;
+}
+
+impl Document for MeiliSponsor {
+    fn id(&self) -> Uuid {
+        self.id
+    }
+
+    fn text(&self) -> Vec<&str> {
+        let mut text = vec![self.name.as_str(), self.short_description.as_str()];
+        text.extend(self.tags.iter().map(String::as_str));
+        text.extend(self.owners.iter().map(String::as_str));
+        for field in self.fields.iter() {
+            text.push(&field.name);
+            text.push(&field.value);
+        }
+        text
+    }
+}
+
+impl Document for MeiliSponsorFavour {
+    fn id(&self) -> Uuid {
+        self.id
+    }
+
+    fn text(&self) -> Vec<&str> {
+        vec![&self.condition]
+    }
+}
+
+struct InvertedIndex<T> {
+    docs: HashMap<Uuid, T>,
+    words: BTreeMap<String, HashSet<Uuid>>,
+}
+
+impl<T> Default for InvertedIndex<T> {
+    fn default() -> Self {
+        Self {
+            docs: HashMap::new(),
+            words: BTreeMap::new(),
+        }
+    }
+}
+
+impl<T: Document> InvertedIndex<T> {
+    fn insert(&mut self, doc: T) {
+        let id = doc.id();
+        self.remove(&id);
+
+        for text in doc.text() {
+            for word in tokenize(text) {
+                self.words.entry(word).or_default().insert(id);
+            }
+        }
+        self.docs.insert(id, doc);
+    }
+
+    fn remove(&mut self, id: &Uuid) {
+        let Some(doc) = self.docs.remove(id) else {
+            return;
+        };
+
+        for text in doc.text() {
+            for word in tokenize(text) {
+                if let Some(ids) = self.words.get_mut(&word) {
+                    ids.remove(id);
+                    if ids.is_empty() {
+                        self.words.remove(&word);
+                    }
+                }
+            }
+        }
+    }
+
+    fn ids_page(&self, offset: usize, limit: usize) -> Vec<Uuid> {
+        let mut ids = self.docs.keys().copied().collect::<Vec<_>>();
+        ids.sort();
+        ids.into_iter().skip(offset).take(limit).collect()
+    }
+
+    fn search(&self, query: &str) -> Vec<T> {
+        let query = tokenize(query);
+
+        let mut scores: HashMap<Uuid, usize> = self.docs.keys().map(|id| (*id, 0)).collect();
+        for word in query.iter() {
+            let mut matched: HashMap<Uuid, usize> = HashMap::new();
+            for (candidate, ids) in self.words.range(word.clone()..) {
+                if !candidate.starts_with(word.as_str()) {
+                    break;
+                }
+
+                let exact = (candidate == word) as usize;
+                for id in ids {
+                    let score = matched.entry(*id).or_default();
+                    *score = (*score).max(exact);
+                }
+            }
+
+            scores.retain(|id, _| matched.contains_key(id));
+            for (id, score) in scores.iter_mut() {
+                *score += matched[id];
+            }
+        }
+
+        let mut hits = scores.into_iter().collect::<Vec<_>>();
+        hits.sort_by(|(a_id, a), (b_id, b)| b.cmp(a).then_with(|| a_id.cmp(b_id)));
+
+        hits.into_iter()
+            .map(|(id, _)| self.docs[&id].clone())
+            .collect()
+    }
+}
+
+// like meilisearch, nothing beyond MAX_TOTAL_HITS is reachable
+fn page<T>(hits: Vec<T>, page: Page) -> Vec<T> {
+    let end = page
+        .offset
+        .checked_add(page.limit)
+        .map_or(MAX_TOTAL_HITS, |end| end.min(MAX_TOTAL_HITS));
+    hits.into_iter().take(end).skip(page.offset).collect()
+}
+
+fn format_sponsor(sponsor: &MeiliSponsor, query: &HashSet<String>) -> Map<String, Value> {
+    let formatted = json!({
+        "name": highlight(&sponsor.name, query),
+        "shortDescription": highlight(&sponsor.short_description, query),
+        "tags": sponsor.tags.iter().map(|t| highlight(t, query)).collect::<Vec<_>>(),
+        "fields": sponsor.fields.iter().map(|f| json!({
+            "name": highlight(&f.name, query),
+            "value": highlight(&f.value, query),
+        })).collect::<Vec<_>>(),
+    });
+
+    match formatted {
+        Value::Object(map) => map,
+        _ => unreachable!(),
+    }
+}
+
+fn highlight(text: &str, query: &HashSet<String>) -> String {
+    let mut highlighted = String::with_capacity(text.len());
+    let mut rest = text;
+    while !rest.is_empty() {
+        let split = rest
+            .find(|c: char| c.is_alphanumeric() != rest.starts_with(char::is_alphanumeric))
+            .unwrap_or(rest.len());
+        let (part, remaining) = rest.split_at(split);
+
+        let lower = part.to_lowercase();
+        if query.iter().any(|q| lower.starts_with(q.as_str())) {
+            highlighted.push_str("<em>");
+            highlighted.push_str(part);
+            highlighted.push_str("</em>");
+        } else {
+            highlighted.push_str(part);
+        }
+        rest = remaining;
+    }
+    highlighted
+}
+
+fn tokenize(text: &str) -> HashSet<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
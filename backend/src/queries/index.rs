@@ -0,0 +1,58 @@
+use async_trait::async_trait;
+use uuid::Uuid;
+
+use crate::models::meili::{
+    FavourSearch, FavourSearchResult, MeiliSponsor, MeiliSponsorFavour, SponsorSearch,
+    SponsorSearchResult,
+};
+
+// backends may return shorter pages
+pub const LIST_PAGE_SIZE: usize = 1000;
+
+#[async_trait]
+pub trait SearchIndex: Send + Sync {
+    async fn setup(&self) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    async fn insert_sponsor(&self, sponsor: &MeiliSponsor) -> anyhow::Result<()>;
+
+    async fn insert_favours(&self, favours: &[MeiliSponsorFavour]) -> anyhow::Result<()>;
+
+    async fn get_sponsors(&self, search: &SponsorSearch) -> anyhow::Result<SponsorSearchResult>;
+
+    async fn get_favours(&self, search: &FavourSearch) -> anyhow::Result<FavourSearchResult>;
+
+    async fn get_favour_ids_of_sponsor(&self, sponsor_uid: &Uuid) -> anyhow::Result<Vec<Uuid>>;
+
+    async fn delete_sponsor(&self, uid: &Uuid) -> anyhow::Result<()>;
+
+    async fn delete_favours(&self, uid: &[Uuid]) -> anyhow::Result<()>;
+
+    // an empty page means there are no more
+    async fn get_sponsor_ids_page(&self, offset: usize, limit: usize) -> anyhow::Result<Vec<Uuid>>;
+
+    async fn get_favour_ids_page(&self, offset: usize, limit: usize) -> anyhow::Result<Vec<Uuid>>;
+
+    async fn get_all_sponsor_ids(&self) -> anyhow::Result<Vec<Uuid>> {
+        let mut ids = Vec::new();
+        loop {
+            let page = self.get_sponsor_ids_page(ids.len(), LIST_PAGE_SIZE).await?;
+            if page.is_empty() {
+                return Ok(ids);
+            }
+            ids.extend(page);
+        }
+    }
+
+    async fn get_all_favour_ids(&self) -> anyhow::Result<Vec<Uuid>> {
+        let mut ids = Vec::new();
+        loop {
+            let page = self.get_favour_ids_page(ids.len(), LIST_PAGE_SIZE).await?;
+            if page.is_empty() {
+                return Ok(ids);
+            }
+            ids.extend(page);
+        }
+    }
+}
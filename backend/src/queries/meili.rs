@@ -1,11 +1,25 @@
+use async_trait::async_trait;
+use chrono::Utc;
+use meilisearch_sdk::documents::DocumentsQuery;
 use meilisearch_sdk::indexes::Index;
+use meilisearch_sdk::search::Selectors;
+use serde::Deserialize;
 use uuid::Uuid;
 
-use crate::models::meili::{MeiliSponsor, MeiliSponsorFavour};
+use crate::models::meili::{
+    DueSort, FavourSearch, FavourSearchResult, MeiliSponsor, MeiliSponsorFavour, MeiliSponsorField,
+    SponsorHit, SponsorSearch, SponsorSearchResult, HIGHLIGHTED_ATTRIBUTES, MAX_TOTAL_HITS,
+};
+use crate::queries::index::SearchIndex;
 
 const INDEX_SPONSORS: &str = "sponsors";
 const INDEX_FAVOURS: &str = "favours";
 
+#[derive(Deserialize)]
+struct DocumentId {
+    id: Uuid,
+}
+
 pub struct MeiliQueries {
     pub client: meilisearch_sdk::Client,
     pub sponsor_index: Index,
@@ -13,61 +27,242 @@ pub struct MeiliQueries {
 }
 
 impl MeiliQueries {
-    pub async fn new(uri: &str, token: &str) -> anyhow::Result<Self> {
-        let client = meilisearch_sdk::Client::new(uri, Some(token));
-
-        client.create_index(INDEX_SPONSORS, Some("id")).await?;
-        client.create_index(INDEX_FAVOURS, Some("id")).await?;
+    pub fn new(uri: &str, token: Option<&str>) -> Self {
+        let client = meilisearch_sdk::Client::new(uri, token);
 
         let sponsor_index = client.index(INDEX_SPONSORS);
         let favours_index = client.index(INDEX_FAVOURS);
 
-        Ok(Self {
+        Self {
             client,
             sponsor_index,
             favours_index,
-        })
+        }
     }
+}
+
+#[async_trait]
+impl SearchIndex for MeiliQueries {
+    async fn setup(&self) -> anyhow::Result<()> {
+        self.client.create_index(INDEX_SPONSORS, Some("id")).await?;
+        self.client.create_index(INDEX_FAVOURS, Some("id")).await?;
 
-    pub async fn insert_sponsor(&self, sponsor: &MeiliSponsor) -> anyhow::Result<()> {
-        self.sponsor_index.add_documents(&[sponsor], Some("id")).await?;
+        self.sponsor_index
+            .set_filterable_attributes([
+                "tags",
+                "fields.name",
+                "fields.value",
+                "fieldPairs",
+                "hasOpenFavours",
+                "owners",
+            ])
+            .await?;
+        self.favours_index
+            .set_filterable_attributes([
+                "sponsor_uid",
+                "completed",
+                "dueUntilTimestamp",
+                "completedAtTimestamp",
+            ])
+            .await?;
+        self.favours_index
+            .set_sortable_attributes(["dueUntilTimestamp"])
+            .await?;
 
         Ok(())
     }
 
-    pub async fn insert_favours(&self, favours: &[MeiliSponsorFavour]) -> anyhow::Result<()> {
-        self.favours_index.add_documents(favours, Some("id")).await?;
+    async fn insert_sponsor(&self, sponsor: &MeiliSponsor) -> anyhow::Result<()> {
+        self.sponsor_index
+            .add_documents(&[sponsor], Some("id"))
+            .await?;
 
         Ok(())
     }
 
-    pub async fn get_sponsors(&self, query: &str) -> anyhow::Result<Vec<MeiliSponsor>> {
-        Ok(self.sponsor_index.search().with_query(query).execute::<MeiliSponsor>().await?.hits
-            .into_iter().map(|s| s.result).collect())
+    async fn insert_favours(&self, favours: &[MeiliSponsorFavour]) -> anyhow::Result<()> {
+        self.favours_index
+            .add_documents(favours, Some("id"))
+            .await?;
+
+        Ok(())
     }
 
-    pub async fn get_favours(&self, query: &str) -> anyhow::Result<Vec<MeiliSponsorFavour>> {
-        Ok(self.favours_index.search().with_query(query).execute::<MeiliSponsorFavour>().await?.hits
-            .into_iter().map(|s| s.result).collect())
+    async fn get_sponsors(&self, search: &SponsorSearch) -> anyhow::Result<SponsorSearchResult> {
+        let filters = sponsor_filters(search);
+        let filters = filters.iter().map(String::as_str).collect::<Vec<_>>();
+
+        let mut query = self.sponsor_index.search();
+        query
+            .with_query(&search.query)
+            .with_offset(search.page.offset)
+            .with_limit(search.page.limit)
+            .with_facets(Selectors::Some(&["tags"]));
+        if search.highlight {
+            query.with_attributes_to_highlight(Selectors::Some(&HIGHLIGHTED_ATTRIBUTES));
+        }
+        if !filters.is_empty() {
+            query.with_array_filter(filters);
+        }
+        let results = query.execute::<MeiliSponsor>().await?;
+
+        Ok(SponsorSearchResult {
+            estimated_total_hits: results.estimated_total_hits.unwrap_or_default(),
+            hits: results
+                .hits
+                .into_iter()
+                .map(|s| SponsorHit {
+                    sponsor: s.result,
+                    formatted: s.formatted_result.map(|mut formatted| {
+                        formatted.retain(|k, _| HIGHLIGHTED_ATTRIBUTES.contains(&k.as_str()));
+                        formatted
+                    }),
+                })
+                .collect(),
+            tag_facets: results
+                .facet_distribution
+                .and_then(|mut f| f.remove("tags"))
+                .unwrap_or_default(),
+        })
     }
 
-    pub async fn delete_sponsor(&self, uid: &Uuid) -> anyhow::Result<()> {
+    async fn get_favours(&self, search: &FavourSearch) -> anyhow::Result<FavourSearchResult> {
+        let filters = favour_filters(search);
+        let filters = filters.iter().map(String::as_str).collect::<Vec<_>>();
+        let sort = match search.sort {
+            Some(DueSort::Asc) => Some(["dueUntilTimestamp:asc"]),
+            Some(DueSort::Desc) => Some(["dueUntilTimestamp:desc"]),
+            None => None,
+        };
+
+        let mut query = self.favours_index.search();
+        query
+            .with_query(&search.query)
+            .with_offset(search.page.offset)
+            .with_limit(search.page.limit);
+        if !filters.is_empty() {
+            query.with_array_filter(filters);
+        }
+        if let Some(sort) = sort.as_ref() {
+            query.with_sort(sort);
+        }
+
+        let results = query.execute::<MeiliSponsorFavour>().await?;
+
+        Ok(FavourSearchResult {
+            estimated_total_hits: results.estimated_total_hits.unwrap_or_default(),
+            hits: results.hits.into_iter().map(|s| s.result).collect(),
+        })
+    }
+
+    async fn get_favour_ids_of_sponsor(&self, sponsor_uid: &Uuid) -> anyhow::Result<Vec<Uuid>> {
+        let filter = format!("sponsor_uid = \"{}\"", sponsor_uid);
+
+        Ok(self
+            .favours_index
+            .search()
+            .with_filter(&filter)
+            .with_limit(MAX_TOTAL_HITS)
+            .with_attributes_to_retrieve(Selectors::Some(&["id"]))
+            .execute::<DocumentId>()
+            .await?
+            .hits
+            .into_iter()
+            .map(|d| d.result.id)
+            .collect())
+    }
+
+    async fn delete_sponsor(&self, uid: &Uuid) -> anyhow::Result<()> {
         self.sponsor_index.delete_document(uid).await?;
 
         Ok(())
     }
 
-    pub async fn delete_favours(&self, uid: &[Uuid]) -> anyhow::Result<()> {
+    async fn delete_favours(&self, uid: &[Uuid]) -> anyhow::Result<()> {
         self.favours_index.delete_documents(uid).await?;
 
         Ok(())
     }
 
-    pub async fn get_all_sponsors(&self) -> anyhow::Result<Vec<MeiliSponsor>> {
-        Ok(self.sponsor_index.get_documents::<MeiliSponsor>().await?.results)
+    async fn get_sponsor_ids_page(&self, offset: usize, limit: usize) -> anyhow::Result<Vec<Uuid>> {
+        get_ids_page(&self.sponsor_index, offset, limit).await
+    }
+
+    async fn get_favour_ids_page(&self, offset: usize, limit: usize) -> anyhow::Result<Vec<Uuid>> {
+        get_ids_page(&self.favours_index, offset, limit).await
+    }
+}
+
+fn sponsor_filters(search: &SponsorSearch) -> Vec<String> {
+    let mut filters = search
+        .tags
+        .iter()
+        .map(|t| format!("tags = {}", quote(t)))
+        .collect::<Vec<_>>();
+
+    match (&search.field_name, &search.field_value) {
+        (Some(name), Some(value)) => {
+            let pair = MeiliSponsorField {
+                name: name.clone(),
+                value: value.clone(),
+            }
+            .pair();
+            filters.push(format!("fieldPairs = {}", quote(&pair)));
+        }
+        (Some(name), None) => filters.push(format!("fields.name = {}", quote(name))),
+        (None, Some(value)) => filters.push(format!("fields.value = {}", quote(value))),
+        (None, None) => {}
     }
 
-    pub async fn get_all_favours(&self) -> anyhow::Result<Vec<MeiliSponsorFavour>> {
-        Ok(self.favours_index.get_documents::<MeiliSponsorFavour>().await?.results)
+    if let Some(open) = search.has_open_favours {
+        filters.push(format!("hasOpenFavours = {}", open));
+    }
+    if let Some(owner) = &search.owner {
+        filters.push(format!("owners = {}", quote(owner)));
+    }
+
+    filters
+}
+
+fn favour_filters(search: &FavourSearch) -> Vec<String> {
+    let mut filters = Vec::new();
+
+    if let Some(completed) = search.completed {
+        filters.push(format!("completed = {}", completed));
     }
+    if let Some(before) = search.due_before {
+        filters.push(format!("dueUntilTimestamp < {}", before.timestamp()));
+    }
+    if let Some(after) = search.due_after {
+        filters.push(format!("dueUntilTimestamp >= {}", after.timestamp()));
+    }
+    if let Some(before) = search.completed_before {
+        filters.push(format!("completedAtTimestamp < {}", before.timestamp()));
+    }
+    if let Some(after) = search.completed_after {
+        filters.push(format!("completedAtTimestamp >= {}", after.timestamp()));
+    }
+    if search.overdue {
+        filters.push("completed = false".to_string());
+        filters.push(format!("dueUntilTimestamp < {}", Utc::now().timestamp()));
+    }
+
+    filters
+}
+
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+async fn get_ids_page(index: &Index, offset: usize, limit: usize) -> anyhow::Result<Vec<Uuid>> {
+    Ok(DocumentsQuery::new(index)
+        .with_offset(offset)
+        .with_limit(limit)
+        .with_fields(["id"])
+        .execute::<DocumentId>()
+        .await?
+        .results
+        .into_iter()
+        .map(|d| d.id)
+        .collect())
 }
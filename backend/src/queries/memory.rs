@@ -0,0 +1,320 @@
+use std::collections::{HashMap, HashSet};
+use std::io::Cursor;
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+use axum::body::Bytes;
+use chrono::Utc;
+use mongodb::bson;
+
+use crate::auth::Role;
+use crate::models::mongo::{
+    ApiToken, Change, GroupRole, LocalAccount, Session, Settings, Sponsor, UserRole,
+};
+use crate::queries::store::{LogoStream, SponsorStore};
+
+#[derive(Default)]
+pub struct MemoryQueries {
+    sponsors: Mutex<Vec<Sponsor>>,
+    settings: Mutex<Option<Settings>>,
+    changes: Mutex<Vec<Change>>,
+    userroles: Mutex<HashMap<String, UserRole>>,
+    grouproles: Mutex<HashMap<String, GroupRole>>,
+    logos: Mutex<HashMap<bson::Uuid, Bytes>>,
+    dirty: Mutex<HashSet<bson::Uuid>>,
+    sessions: Mutex<HashMap<bson::Uuid, Session>>,
+    api_tokens: Mutex<Vec<ApiToken>>,
+    local_accounts: Mutex<HashMap<String, LocalAccount>>,
+}
+
+impl MemoryQueries {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl SponsorStore for MemoryQueries {
+    async fn insert(&self, sponsor: &Sponsor) -> anyhow::Result<()> {
+        let mut sponsors = self.sponsors.lock().unwrap();
+        if sponsors.iter().any(|s| s.uid == sponsor.uid) {
+            anyhow::bail!("duplicate sponsor uid {}", sponsor.uid);
+        }
+
+        sponsors.push(sponsor.clone());
+        Ok(())
+    }
+
+    async fn get(&self, uid: bson::Uuid) -> anyhow::Result<Option<Sponsor>> {
+        Ok(self
+            .sponsors
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|s| s.uid == uid)
+            .cloned())
+    }
+
+    async fn get_many(&self, uids: &[bson::Uuid]) -> anyhow::Result<Vec<Sponsor>> {
+        let sponsors = self.sponsors.lock().unwrap();
+        Ok(uids
+            .iter()
+            .filter_map(|uid| sponsors.iter().find(|s| &s.uid == uid).cloned())
+            .collect())
+    }
+
+    async fn delete(&self, uid: &bson::Uuid) -> anyhow::Result<()> {
+        self.sponsors.lock().unwrap().retain(|s| &s.uid != uid);
+        Ok(())
+    }
+
+    async fn update(&self, uid: &bson::Uuid, sponsor: &Sponsor) -> anyhow::Result<()> {
+        if let Some(s) = self
+            .sponsors
+            .lock()
+            .unwrap()
+            .iter_mut()
+            .find(|s| &s.uid == uid)
+        {
+            *s = sponsor.clone();
+        }
+        Ok(())
+    }
+
+    async fn get_all(&self) -> anyhow::Result<Vec<Sponsor>> {
+        Ok(self.sponsors.lock().unwrap().clone())
+    }
+
+    async fn get_owned(&self, email: &str) -> anyhow::Result<Vec<Sponsor>> {
+        Ok(self
+            .sponsors
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|s| s.owners.contains(email))
+            .cloned()
+            .collect())
+    }
+
+    async fn get_settings(&self) -> anyhow::Result<Settings> {
+        Ok(self.settings.lock().unwrap().clone().unwrap_or_default())
+    }
+
+    async fn update_settings(&self, settings: &Settings) -> anyhow::Result<()> {
+        *self.settings.lock().unwrap() = Some(settings.clone());
+        Ok(())
+    }
+
+    async fn upload_logo(&self, sponsor_uid: &bson::Uuid, file: Bytes) -> anyhow::Result<()> {
+        self.logos.lock().unwrap().insert(*sponsor_uid, file);
+        Ok(())
+    }
+
+    async fn delete_logo(&self, sponsor_uid: &bson::Uuid) -> anyhow::Result<()> {
+        self.logos.lock().unwrap().remove(sponsor_uid);
+        Ok(())
+    }
+
+    async fn get_logo(&self, sponsor_uid: &bson::Uuid) -> anyhow::Result<Option<LogoStream>> {
+        Ok(self
+            .logos
+            .lock()
+            .unwrap()
+            .get(sponsor_uid)
+            .cloned()
+            .map(|bytes| Box::pin(Cursor::new(bytes)) as LogoStream))
+    }
+
+    async fn add_change(&self, change: &Change) -> anyhow::Result<()> {
+        self.changes.lock().unwrap().push(change.clone());
+        if let Some(uid) = change.what.sponsor_uid() {
+            self.mark_dirty(&uid).await?;
+        }
+        Ok(())
+    }
+
+    async fn get_changes(&self, offset: u64) -> anyhow::Result<(Vec<Change>, u64)> {
+        let changes = self.changes.lock().unwrap();
+        let mut sorted = changes.iter().cloned().collect::<Vec<_>>();
+        sorted.sort_by_key(|c| std::cmp::Reverse(c.when));
+
+        let page = sorted.into_iter().skip(offset as usize).take(100).collect();
+        Ok((page, changes.len() as u64))
+    }
+
+    async fn mark_dirty(&self, sponsor_uid: &bson::Uuid) -> anyhow::Result<()> {
+        self.dirty.lock().unwrap().insert(*sponsor_uid);
+        Ok(())
+    }
+
+    async fn take_dirty(&self) -> anyhow::Result<Vec<bson::Uuid>> {
+        Ok(self.dirty.lock().unwrap().drain().collect())
+    }
+
+    async fn add_or_update_role(&self, model: &UserRole) -> anyhow::Result<()> {
+        self.userroles
+            .lock()
+            .unwrap()
+            .insert(model.email.clone(), model.clone());
+        Ok(())
+    }
+
+    async fn get_user_role(&self, email: &str) -> anyhow::Result<Option<Role>> {
+        Ok(self.userroles.lock().unwrap().get(email).map(|r| r.role))
+    }
+
+    async fn set_group_role(&self, email: &str, role: Option<Role>) -> anyhow::Result<()> {
+        let mut grouproles = self.grouproles.lock().unwrap();
+        match role {
+            Some(role) => grouproles.insert(
+                email.to_string(),
+                GroupRole {
+                    email: email.to_string(),
+                    role,
+                    updated: Utc::now(),
+                },
+            ),
+            None => grouproles.remove(email),
+        };
+        Ok(())
+    }
+
+    async fn get_group_role(&self, email: &str) -> anyhow::Result<Option<GroupRole>> {
+        Ok(self.grouproles.lock().unwrap().get(email).cloned())
+    }
+
+    async fn get_all_admins(&self) -> anyhow::Result<Vec<UserRole>> {
+        Ok(self
+            .userroles
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|r| r.role == Role::ADMIN)
+            .cloned()
+            .collect())
+    }
+
+    async fn get_all_roles(&self) -> anyhow::Result<Vec<UserRole>> {
+        Ok(self.userroles.lock().unwrap().values().cloned().collect())
+    }
+
+    async fn delete_role(&self, email: &str) -> anyhow::Result<()> {
+        self.userroles.lock().unwrap().remove(email);
+        Ok(())
+    }
+
+    async fn create_session(&self, session: &Session) -> anyhow::Result<()> {
+        self.sessions
+            .lock()
+            .unwrap()
+            .insert(session.uid, session.clone());
+        Ok(())
+    }
+
+    async fn get_session(&self, uid: &bson::Uuid) -> anyhow::Result<Option<Session>> {
+        Ok(self.sessions.lock().unwrap().get(uid).cloned())
+    }
+
+    async fn rotate_session(
+        &self,
+        uid: &bson::Uuid,
+        old_hash: &str,
+        new_hash: &str,
+    ) -> anyhow::Result<Option<Session>> {
+        let mut sessions = self.sessions.lock().unwrap();
+        let Some(session) = sessions
+            .get_mut(uid)
+            .filter(|s| s.is_active() && s.refresh_token_hash == old_hash)
+        else {
+            return Ok(None);
+        };
+
+        let previous = session.clone();
+        session.refresh_token_hash = new_hash.to_string();
+        Ok(Some(previous))
+    }
+
+    async fn revoke_session(&self, uid: &bson::Uuid) -> anyhow::Result<()> {
+        if let Some(session) = self.sessions.lock().unwrap().get_mut(uid) {
+            session.revoked = true;
+        }
+        Ok(())
+    }
+
+    async fn revoke_user_sessions(&self, email: &str) -> anyhow::Result<u64> {
+        let mut revoked = 0;
+        for session in self.sessions.lock().unwrap().values_mut() {
+            if session.email == email && !session.revoked {
+                session.revoked = true;
+                revoked += 1;
+            }
+        }
+        Ok(revoked)
+    }
+
+    async fn get_local_account(&self, email: &str) -> anyhow::Result<Option<LocalAccount>> {
+        Ok(self.local_accounts.lock().unwrap().get(email).cloned())
+    }
+
+    async fn get_local_accounts(&self) -> anyhow::Result<Vec<LocalAccount>> {
+        Ok(self
+            .local_accounts
+            .lock()
+            .unwrap()
+            .values()
+            .cloned()
+            .collect())
+    }
+
+    async fn upsert_local_account(&self, account: &LocalAccount) -> anyhow::Result<()> {
+        self.local_accounts
+            .lock()
+            .unwrap()
+            .insert(account.email.clone(), account.clone());
+        Ok(())
+    }
+
+    async fn delete_local_account(&self, email: &str) -> anyhow::Result<bool> {
+        Ok(self.local_accounts.lock().unwrap().remove(email).is_some())
+    }
+
+    async fn create_api_token(&self, token: &ApiToken) -> anyhow::Result<()> {
+        self.api_tokens.lock().unwrap().push(token.clone());
+        Ok(())
+    }
+
+    async fn get_api_token(&self, uid: &bson::Uuid) -> anyhow::Result<Option<ApiToken>> {
+        Ok(self
+            .api_tokens
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|t| &t.uid == uid)
+            .cloned())
+    }
+
+    async fn get_api_tokens(&self, email: &str) -> anyhow::Result<Vec<ApiToken>> {
+        Ok(self
+            .api_tokens
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|t| t.email == email)
+            .cloned()
+            .collect())
+    }
+
+    async fn delete_api_token(&self, uid: &bson::Uuid, email: &str) -> anyhow::Result<bool> {
+        let mut tokens = self.api_tokens.lock().unwrap();
+        let len = tokens.len();
+        tokens.retain(|t| !(&t.uid == uid && t.email == email));
+        Ok(tokens.len() != len)
+    }
+
+    async fn delete_user_api_tokens(&self, email: &str) -> anyhow::Result<u64> {
+        let mut tokens = self.api_tokens.lock().unwrap();
+        let len = tokens.len();
+        tokens.retain(|t| t.email != email);
+        Ok((len - tokens.len()) as u64)
+    }
+}
@@ -1,2 +1,6 @@
+pub mod embedded;
+pub mod index;
 pub mod meili;
-pub mod mongo;
\ No newline at end of file
+pub mod memory;
+pub mod mongo;
+pub mod store;
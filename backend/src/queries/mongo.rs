@@ -1,17 +1,23 @@
+use std::collections::HashMap;
 use std::time::Duration;
 
+use async_trait::async_trait;
 use axum::body::Bytes;
+use chrono::Utc;
 use futures::{AsyncWriteExt, StreamExt};
 use mongodb::bson::doc;
 use mongodb::options::{
     ClientOptions, FindOneAndReplaceOptions, FindOptions, GridFsBucketOptions, GridFsFindOptions,
-    ReplaceOptions,
+    IndexOptions, ReplaceOptions,
 };
 use mongodb::{bson, Collection, GridFsBucket, IndexModel};
 use tokio_util::compat::FuturesAsyncReadCompatExt;
 
 use crate::auth::Role;
-use crate::models::mongo::{Change, Settings, Sponsor, UserRole};
+use crate::models::mongo::{
+    ApiToken, Change, DirtySponsor, GroupRole, LocalAccount, Session, Settings, Sponsor, UserRole,
+};
+use crate::queries::store::{LogoStream, SponsorStore};
 
 const DB_NAME: &str = "sponsormanager";
 
@@ -22,6 +28,11 @@ pub struct MongoQueries {
     pub settings_collection: Collection<Settings>,
     pub change_collection: Collection<Change>,
     pub userrole_collection: Collection<UserRole>,
+    pub grouprole_collection: Collection<GroupRole>,
+    pub dirty_collection: Collection<DirtySponsor>,
+    pub session_collection: Collection<Session>,
+    pub api_token_collection: Collection<ApiToken>,
+    pub local_account_collection: Collection<LocalAccount>,
     pub logo_bucket: GridFsBucket,
 }
 
@@ -37,6 +48,11 @@ impl MongoQueries {
         let settings_collection = db.collection("settings");
         let change_collection = db.collection("changes");
         let userrole_collection = db.collection("userroles");
+        let grouprole_collection = db.collection("grouproles");
+        let dirty_collection = db.collection("dirty");
+        let session_collection = db.collection("sessions");
+        let api_token_collection = db.collection("apitokens");
+        let local_account_collection = db.collection("localaccounts");
         let logo_bucket = db.gridfs_bucket(
             GridFsBucketOptions::builder()
                 .bucket_name(Some("logos".to_string()))
@@ -51,6 +67,24 @@ impl MongoQueries {
         userrole_collection
             .create_index(IndexModel::builder().keys(doc! {"email": 1}).build(), None)
             .await?;
+        grouprole_collection
+            .create_index(IndexModel::builder().keys(doc! {"email": 1}).build(), None)
+            .await?;
+        session_collection
+            .create_index(IndexModel::builder().keys(doc! {"email": 1}).build(), None)
+            .await?;
+        session_collection
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! {"expires": 1})
+                    .options(IndexOptions::builder().expire_after(Duration::ZERO).build())
+                    .build(),
+                None,
+            )
+            .await?;
+        api_token_collection
+            .create_index(IndexModel::builder().keys(doc! {"email": 1}).build(), None)
+            .await?;
 
         Ok(Self {
             client,
@@ -59,30 +93,52 @@ impl MongoQueries {
             settings_collection,
             change_collection,
             userrole_collection,
+            grouprole_collection,
+            dirty_collection,
+            session_collection,
+            api_token_collection,
+            local_account_collection,
             logo_bucket,
         })
     }
+}
 
-    pub async fn insert(&self, sponsor: &Sponsor) -> anyhow::Result<()> {
+#[async_trait]
+impl SponsorStore for MongoQueries {
+    async fn insert(&self, sponsor: &Sponsor) -> anyhow::Result<()> {
         self.sponsor_collection.insert_one(sponsor, None).await?;
         Ok(())
     }
 
-    pub async fn get(&self, uid: bson::Uuid) -> anyhow::Result<Option<Sponsor>> {
+    async fn get(&self, uid: bson::Uuid) -> anyhow::Result<Option<Sponsor>> {
         Ok(self
             .sponsor_collection
             .find_one(Some(doc! {"_id": uid}), None)
             .await?)
     }
 
-    pub async fn delete(&self, uid: &bson::Uuid) -> anyhow::Result<()> {
+    async fn get_many(&self, uids: &[bson::Uuid]) -> anyhow::Result<Vec<Sponsor>> {
+        let mut cursor = self
+            .sponsor_collection
+            .find(doc! {"_id": {"$in": uids}}, None)
+            .await?;
+        let mut sponsors = HashMap::new();
+        while let Some(sponsor) = cursor.next().await {
+            let sponsor = sponsor?;
+            sponsors.insert(sponsor.uid, sponsor);
+        }
+
+        Ok(uids.iter().filter_map(|uid| sponsors.remove(uid)).collect())
+    }
+
+    async fn delete(&self, uid: &bson::Uuid) -> anyhow::Result<()> {
         self.sponsor_collection
             .delete_one(doc! {"_id": uid}, None)
             .await?;
         Ok(())
     }
 
-    pub async fn update(&self, uid: &bson::Uuid, sponsor: &Sponsor) -> anyhow::Result<()> {
+    async fn update(&self, uid: &bson::Uuid, sponsor: &Sponsor) -> anyhow::Result<()> {
         self.sponsor_collection
             .replace_one(
                 doc! {"_id": uid},
@@ -93,7 +149,7 @@ impl MongoQueries {
         Ok(())
     }
 
-    pub async fn get_all(&self) -> anyhow::Result<Vec<Sponsor>> {
+    async fn get_all(&self) -> anyhow::Result<Vec<Sponsor>> {
         let mut cursor = self.sponsor_collection.find(None, None).await?;
         let mut sponsors = Vec::new();
         while let Some(sponsor) = cursor.next().await {
@@ -102,7 +158,19 @@ impl MongoQueries {
         Ok(sponsors)
     }
 
-    pub async fn get_settings(&self) -> anyhow::Result<Settings> {
+    async fn get_owned(&self, email: &str) -> anyhow::Result<Vec<Sponsor>> {
+        let mut cursor = self
+            .sponsor_collection
+            .find(doc! {"owners": email}, None)
+            .await?;
+        let mut sponsors = Vec::new();
+        while let Some(sponsor) = cursor.next().await {
+            sponsors.push(sponsor?);
+        }
+        Ok(sponsors)
+    }
+
+    async fn get_settings(&self) -> anyhow::Result<Settings> {
         Ok(self
             .settings_collection
             .find_one(None, None)
@@ -110,7 +178,7 @@ impl MongoQueries {
             .unwrap_or_default())
     }
 
-    pub async fn update_settings(&self, settings: &Settings) -> anyhow::Result<()> {
+    async fn update_settings(&self, settings: &Settings) -> anyhow::Result<()> {
         self.settings_collection
             .replace_one(
                 doc! {},
@@ -121,7 +189,7 @@ impl MongoQueries {
         Ok(())
     }
 
-    pub async fn upload_logo(&self, sponsor_uid: &bson::Uuid, file: Bytes) -> anyhow::Result<()> {
+    async fn upload_logo(&self, sponsor_uid: &bson::Uuid, file: Bytes) -> anyhow::Result<()> {
         let _ = self.delete_logo(sponsor_uid).await; // ignore errors
 
         let mut stream = self
@@ -133,7 +201,7 @@ impl MongoQueries {
         Ok(())
     }
 
-    pub async fn delete_logo(&self, sponsor_uid: &bson::Uuid) -> anyhow::Result<()> {
+    async fn delete_logo(&self, sponsor_uid: &bson::Uuid) -> anyhow::Result<()> {
         let mut cursor = self
             .logo_bucket
             .find(
@@ -150,16 +218,13 @@ impl MongoQueries {
         Ok(())
     }
 
-    pub async fn get_logo(
-        &self,
-        sponsor_uid: &bson::Uuid,
-    ) -> anyhow::Result<Option<impl tokio::io::AsyncRead>> {
-        let stream = match self
+    async fn get_logo(&self, sponsor_uid: &bson::Uuid) -> anyhow::Result<Option<LogoStream>> {
+        let stream: LogoStream = match self
             .logo_bucket
             .open_download_stream_by_name(sponsor_uid.to_string(), None)
             .await
         {
-            Ok(s) => s.compat(),
+            Ok(s) => Box::pin(s.compat()),
             Err(e) => {
                 if e.to_string().contains("FileNotFound") {
                     return Ok(None);
@@ -172,13 +237,16 @@ impl MongoQueries {
         Ok(Some(stream))
     }
 
-    pub async fn add_change(&self, change: &Change) -> anyhow::Result<()> {
+    async fn add_change(&self, change: &Change) -> anyhow::Result<()> {
         self.change_collection.insert_one(change, None).await?;
+        if let Some(uid) = change.what.sponsor_uid() {
+            self.mark_dirty(&uid).await?;
+        }
 
         Ok(())
     }
 
-    pub async fn get_changes(&self, offset: u64) -> anyhow::Result<(Vec<Change>, u64)> {
+    async fn get_changes(&self, offset: u64) -> anyhow::Result<(Vec<Change>, u64)> {
         let mut cursor = self
             .change_collection
             .find(
@@ -203,7 +271,46 @@ impl MongoQueries {
         Ok((changes, total))
     }
 
-    pub async fn add_or_update_role(&self, model: &UserRole) -> anyhow::Result<()> {
+    async fn mark_dirty(&self, sponsor_uid: &bson::Uuid) -> anyhow::Result<()> {
+        self.dirty_collection
+            .insert_one(
+                DirtySponsor {
+                    id: None,
+                    sponsor_uid: *sponsor_uid,
+                },
+                None,
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn take_dirty(&self) -> anyhow::Result<Vec<bson::Uuid>> {
+        let dirty = self
+            .dirty_collection
+            .find(doc! {}, None)
+            .await?
+            .collect::<Vec<mongodb::error::Result<DirtySponsor>>>()
+            .await
+            .into_iter()
+            .collect::<mongodb::error::Result<Vec<DirtySponsor>>>()?;
+        if dirty.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        // delete by _id so entries queued in the meantime survive
+        let ids = dirty.iter().filter_map(|d| d.id).collect::<Vec<_>>();
+        self.dirty_collection
+            .delete_many(doc! {"_id": {"$in": ids}}, None)
+            .await?;
+
+        let mut uids = dirty.into_iter().map(|d| d.sponsor_uid).collect::<Vec<_>>();
+        uids.sort_by_key(|u| u.bytes());
+        uids.dedup();
+        Ok(uids)
+    }
+
+    async fn add_or_update_role(&self, model: &UserRole) -> anyhow::Result<()> {
         self.userrole_collection
             .find_one_and_replace(
                 doc! {"email": &model.email},
@@ -217,7 +324,7 @@ impl MongoQueries {
         Ok(())
     }
 
-    pub async fn get_user_role(&self, email: &str) -> anyhow::Result<Option<Role>> {
+    async fn get_user_role(&self, email: &str) -> anyhow::Result<Option<Role>> {
         let role = self
             .userrole_collection
             .find_one(doc! {"email": &email}, None)
@@ -229,7 +336,39 @@ impl MongoQueries {
         }
     }
 
-    pub async fn get_all_admins(&self) -> anyhow::Result<Vec<UserRole>> {
+    async fn set_group_role(&self, email: &str, role: Option<Role>) -> anyhow::Result<()> {
+        match role {
+            Some(role) => {
+                self.grouprole_collection
+                    .replace_one(
+                        doc! {"email": email},
+                        GroupRole {
+                            email: email.to_string(),
+                            role,
+                            updated: Utc::now(),
+                        },
+                        ReplaceOptions::builder().upsert(true).build(),
+                    )
+                    .await?;
+            }
+            None => {
+                self.grouprole_collection
+                    .delete_one(doc! {"email": email}, None)
+                    .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn get_group_role(&self, email: &str) -> anyhow::Result<Option<GroupRole>> {
+        Ok(self
+            .grouprole_collection
+            .find_one(doc! {"email": email}, None)
+            .await?)
+    }
+
+    async fn get_all_admins(&self) -> anyhow::Result<Vec<UserRole>> {
         let c = self
             .userrole_collection
             .find(doc! {"role": "ADMIN"}, None)
@@ -242,4 +381,166 @@ impl MongoQueries {
 
         Ok(v)
     }
+
+    async fn get_all_roles(&self) -> anyhow::Result<Vec<UserRole>> {
+        let v = self
+            .userrole_collection
+            .find(doc! {}, None)
+            .await?
+            .collect::<Vec<mongodb::error::Result<UserRole>>>()
+            .await
+            .into_iter()
+            .collect::<mongodb::error::Result<Vec<UserRole>>>()?;
+
+        Ok(v)
+    }
+
+    async fn delete_role(&self, email: &str) -> anyhow::Result<()> {
+        self.userrole_collection
+            .delete_one(doc! {"email": email}, None)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn create_session(&self, session: &Session) -> anyhow::Result<()> {
+        self.session_collection.insert_one(session, None).await?;
+
+        Ok(())
+    }
+
+    async fn get_session(&self, uid: &bson::Uuid) -> anyhow::Result<Option<Session>> {
+        Ok(self
+            .session_collection
+            .find_one(doc! {"_id": uid}, None)
+            .await?)
+    }
+
+    async fn rotate_session(
+        &self,
+        uid: &bson::Uuid,
+        old_hash: &str,
+        new_hash: &str,
+    ) -> anyhow::Result<Option<Session>> {
+        Ok(self
+            .session_collection
+            .find_one_and_update(
+                doc! {
+                    "_id": uid,
+                    "refresh_token_hash": old_hash,
+                    "revoked": false,
+                    "expires": {"$gt": bson::DateTime::now()},
+                },
+                doc! {"$set": {"refresh_token_hash": new_hash}},
+                None,
+            )
+            .await?)
+    }
+
+    async fn revoke_session(&self, uid: &bson::Uuid) -> anyhow::Result<()> {
+        self.session_collection
+            .update_one(doc! {"_id": uid}, doc! {"$set": {"revoked": true}}, None)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn revoke_user_sessions(&self, email: &str) -> anyhow::Result<u64> {
+        let result = self
+            .session_collection
+            .update_many(
+                doc! {"email": email, "revoked": false},
+                doc! {"$set": {"revoked": true}},
+                None,
+            )
+            .await?;
+
+        Ok(result.modified_count)
+    }
+
+    async fn get_local_account(&self, email: &str) -> anyhow::Result<Option<LocalAccount>> {
+        Ok(self
+            .local_account_collection
+            .find_one(doc! {"_id": email}, None)
+            .await?)
+    }
+
+    async fn get_local_accounts(&self) -> anyhow::Result<Vec<LocalAccount>> {
+        let v = self
+            .local_account_collection
+            .find(doc! {}, None)
+            .await?
+            .collect::<Vec<_>>()
+            .await
+            .into_iter()
+            .collect::<mongodb::error::Result<Vec<LocalAccount>>>()?;
+
+        Ok(v)
+    }
+
+    async fn upsert_local_account(&self, account: &LocalAccount) -> anyhow::Result<()> {
+        self.local_account_collection
+            .replace_one(
+                doc! {"_id": &account.email},
+                account,
+                ReplaceOptions::builder().upsert(true).build(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn delete_local_account(&self, email: &str) -> anyhow::Result<bool> {
+        let result = self
+            .local_account_collection
+            .delete_one(doc! {"_id": email}, None)
+            .await?;
+
+        Ok(result.deleted_count > 0)
+    }
+
+    async fn create_api_token(&self, token: &ApiToken) -> anyhow::Result<()> {
+        self.api_token_collection.insert_one(token, None).await?;
+
+        Ok(())
+    }
+
+    async fn get_api_token(&self, uid: &bson::Uuid) -> anyhow::Result<Option<ApiToken>> {
+        Ok(self
+            .api_token_collection
+            .find_one(doc! {"_id": uid}, None)
+            .await?)
+    }
+
+    async fn get_api_tokens(&self, email: &str) -> anyhow::Result<Vec<ApiToken>> {
+        let options = FindOptions::builder().sort(doc! {"created": 1}).build();
+        let v = self
+            .api_token_collection
+            .find(doc! {"email": email}, options)
+            .await?
+            .collect::<Vec<_>>()
+            .await
+            .into_iter()
+            .collect::<mongodb::error::Result<Vec<ApiToken>>>()?;
+
+        Ok(v)
+    }
+
+    async fn delete_api_token(&self, uid: &bson::Uuid, email: &str) -> anyhow::Result<bool> {
+        let result = self
+            .api_token_collection
+            .delete_one(doc! {"_id": uid, "email": email}, None)
+            .await?;
+
+        Ok(result.deleted_count > 0)
+    }
+
+    async fn delete_user_api_tokens(&self, email: &str) -> anyhow::Result<u64> {
+        let result = self
+            .api_token_collection
+            .delete_many(doc! {"email": email}, None)
+            .await?;
+
+        Ok(result.deleted_count)
+    }
 }
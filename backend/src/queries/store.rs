@@ -0,0 +1,97 @@
+use std::pin::Pin;
+
+use async_trait::async_trait;
+use axum::body::Bytes;
+use mongodb::bson;
+
+use crate::auth::Role;
+use crate::models::mongo::{
+    ApiToken, Change, GroupRole, LocalAccount, Session, Settings, Sponsor, UserRole,
+};
+
+pub type LogoStream = Pin<Box<dyn tokio::io::AsyncRead + Send>>;
+
+#[async_trait]
+pub trait SponsorStore: Send + Sync {
+    async fn insert(&self, sponsor: &Sponsor) -> anyhow::Result<()>;
+
+    async fn get(&self, uid: bson::Uuid) -> anyhow::Result<Option<Sponsor>>;
+
+    async fn get_many(&self, uids: &[bson::Uuid]) -> anyhow::Result<Vec<Sponsor>>;
+
+    async fn delete(&self, uid: &bson::Uuid) -> anyhow::Result<()>;
+
+    async fn update(&self, uid: &bson::Uuid, sponsor: &Sponsor) -> anyhow::Result<()>;
+
+    async fn get_all(&self) -> anyhow::Result<Vec<Sponsor>>;
+
+    async fn get_owned(&self, email: &str) -> anyhow::Result<Vec<Sponsor>>;
+
+    async fn get_settings(&self) -> anyhow::Result<Settings>;
+
+    async fn update_settings(&self, settings: &Settings) -> anyhow::Result<()>;
+
+    async fn upload_logo(&self, sponsor_uid: &bson::Uuid, file: Bytes) -> anyhow::Result<()>;
+
+    async fn delete_logo(&self, sponsor_uid: &bson::Uuid) -> anyhow::Result<()>;
+
+    async fn get_logo(&self, sponsor_uid: &bson::Uuid) -> anyhow::Result<Option<LogoStream>>;
+
+    // call after writing the sponsor, otherwise the sync may index the old version
+    async fn add_change(&self, change: &Change) -> anyhow::Result<()>;
+
+    async fn get_changes(&self, offset: u64) -> anyhow::Result<(Vec<Change>, u64)>;
+
+    async fn mark_dirty(&self, sponsor_uid: &bson::Uuid) -> anyhow::Result<()>;
+
+    async fn take_dirty(&self) -> anyhow::Result<Vec<bson::Uuid>>;
+
+    async fn add_or_update_role(&self, model: &UserRole) -> anyhow::Result<()>;
+
+    async fn get_user_role(&self, email: &str) -> anyhow::Result<Option<Role>>;
+
+    async fn set_group_role(&self, email: &str, role: Option<Role>) -> anyhow::Result<()>;
+
+    // stale entries are returned too
+    async fn get_group_role(&self, email: &str) -> anyhow::Result<Option<GroupRole>>;
+
+    async fn get_all_admins(&self) -> anyhow::Result<Vec<UserRole>>;
+
+    async fn get_all_roles(&self) -> anyhow::Result<Vec<UserRole>>;
+
+    async fn delete_role(&self, email: &str) -> anyhow::Result<()>;
+
+    async fn create_session(&self, session: &Session) -> anyhow::Result<()>;
+
+    async fn get_session(&self, uid: &bson::Uuid) -> anyhow::Result<Option<Session>>;
+
+    // a single step, so a refresh token can only be redeemed once
+    async fn rotate_session(
+        &self,
+        uid: &bson::Uuid,
+        old_hash: &str,
+        new_hash: &str,
+    ) -> anyhow::Result<Option<Session>>;
+
+    async fn revoke_session(&self, uid: &bson::Uuid) -> anyhow::Result<()>;
+
+    async fn revoke_user_sessions(&self, email: &str) -> anyhow::Result<u64>;
+
+    async fn get_local_account(&self, email: &str) -> anyhow::Result<Option<LocalAccount>>;
+
+    async fn get_local_accounts(&self) -> anyhow::Result<Vec<LocalAccount>>;
+
+    async fn upsert_local_account(&self, account: &LocalAccount) -> anyhow::Result<()>;
+
+    async fn delete_local_account(&self, email: &str) -> anyhow::Result<bool>;
+
+    async fn create_api_token(&self, token: &ApiToken) -> anyhow::Result<()>;
+
+    async fn get_api_token(&self, uid: &bson::Uuid) -> anyhow::Result<Option<ApiToken>>;
+
+    async fn get_api_tokens(&self, email: &str) -> anyhow::Result<Vec<ApiToken>>;
+
+    async fn delete_api_token(&self, uid: &bson::Uuid, email: &str) -> anyhow::Result<bool>;
+
+    async fn delete_user_api_tokens(&self, email: &str) -> anyhow::Result<u64>;
+}
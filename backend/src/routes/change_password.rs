@@ -0,0 +1,64 @@
+use axum::extract::State;
+use axum::http::header::SET_COOKIE;
+use axum::response::IntoResponse;
+use axum::Json;
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::auth::{AuthProvider, User};
+use crate::error::AppError;
+use crate::local_auth::MIN_PASSWORD_LENGTH;
+use crate::models::mongo::{Change, ChangeType};
+use crate::{local_auth, session, AppResult, AppState};
+
+#[derive(Deserialize)]
+pub struct ChangePassword {
+    #[serde(rename = "oldPassword")]
+    old_password: String,
+    #[serde(rename = "newPassword")]
+    new_password: String,
+}
+
+pub async fn change_password(
+    state: State<AppState>,
+    user: User,
+    Json(body): Json<ChangePassword>,
+) -> AppResult {
+    if state.config.auth_provider != AuthProvider::Local {
+        return Err(AppError::new(404, "local login is not enabled"));
+    }
+    if user.token.is_some() {
+        return Err(AppError::new(403, "api tokens cannot change passwords"));
+    }
+    if body.new_password.len() < MIN_PASSWORD_LENGTH {
+        return Err(AppError::new(
+            400,
+            format!("password must be at least {} characters", MIN_PASSWORD_LENGTH),
+        ));
+    }
+    if local_auth::login(&state, &user.email, &body.old_password)
+        .await?
+        .is_none()
+    {
+        return Err(AppError::new(403, "old password is wrong"));
+    }
+
+    local_auth::set_account(state.store.as_ref(), &user.email, &user.sub, &body.new_password).await?;
+    state
+        .store
+        .add_change(&Change::new(
+            &user.email,
+            ChangeType::ChangeLocalAccount(user.email.clone()),
+        ))
+        .await?;
+
+    state.store.revoke_user_sessions(&user.email).await?;
+    let tokens = session::start_session(&state, user).await?;
+
+    let mut response = Json(json!({"token": &tokens.access_token, "csrfToken": &tokens.csrf_token})).into_response();
+    for cookie in session::session_cookies(&tokens)? {
+        response.headers_mut().append(SET_COOKIE, cookie);
+    }
+
+    Ok(response)
+}
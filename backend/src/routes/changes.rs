@@ -7,9 +7,9 @@ use crate::{AppResult, AppState};
 use crate::auth::User;
 
 pub async fn changes(state: State<AppState>, _user: User, Path(offset): Path<String>) -> AppResult {
-    let offset = offset.parse::<u64>()?.max(0);
+    let offset = offset.parse::<u64>()?;
 
-    let (changes, total) = state.mongo.get_changes(offset).await?;
+    let (changes, total) = state.store.get_changes(offset).await?;
 
     Ok(Json(json!({"changes": changes, "total": total})).into_response())
 }
@@ -1,16 +1,17 @@
+use std::collections::HashSet;
+
 use axum::extract::State;
 use axum::Json;
 use axum::response::IntoResponse;
 use uuid::Uuid;
 
 use crate::{AppResult, AppState, misc};
-use crate::auth::User;
+use crate::auth::RequireEdit;
 use crate::error::AppError;
-use crate::models::meili::MeiliSponsorFavour;
 use crate::models::mongo::{Change, ChangeType, Sponsor, SponsorFavour, SponsorField};
 use crate::models::rest::RestSponsor;
 
-pub async fn create(state: State<AppState>, user: User, payload: Json<RestSponsor>) -> AppResult {
+pub async fn create(state: State<AppState>, RequireEdit(user): RequireEdit, payload: Json<RestSponsor>) -> AppResult {
     let mut payload = payload.0;
     payload.uid = Some(Uuid::new_v4());
 
@@ -21,12 +22,17 @@ pub async fn create(state: State<AppState>, user: User, payload: Json<RestSponso
         image_url: payload.image_url,
         fields: payload.fields.into_iter().map(|field| SponsorField { name: field.name, value: field.value }).collect(),
         tags: payload.tags,
+        owners: match payload.owners.map(misc::normalize_emails) {
+            Some(owners) if !owners.is_empty() => owners,
+            _ => HashSet::from([misc::normalize_email(&user.email)]),
+        },
         favours: payload.favours.into_iter().map(|favour| SponsorFavour {
             uid: Uuid::new_v4().into(),
             sponsor_uid: payload.uid.unwrap().into(),
             condition: favour.condition,
             completed: favour.completed,
             due_until: favour.due_until,
+            completed_at: SponsorFavour::completed_at(favour.completed, None),
         }).collect(),
     };
 
@@ -34,13 +40,8 @@ pub async fn create(state: State<AppState>, user: User, payload: Json<RestSponso
         return Err(AppError::new(400, e.to_string()));
     }
 
-    state.mongo.add_change(&Change::new(user.email, ChangeType::AddSponsor(mongo_sponsor.clone()))).await?;
-    state.mongo.insert(&mongo_sponsor).await?;
-
-    state.meili.insert_sponsor(&mongo_sponsor.clone().into()).await?;
-    state.meili.insert_favours(
-        &MeiliSponsorFavour::from_sponsor_vec(&mongo_sponsor.favours)
-    ).await?;
+    state.store.insert(&mongo_sponsor).await?;
+    state.store.add_change(&Change::new(user.email, ChangeType::AddSponsor(mongo_sponsor.clone()))).await?;
 
     Ok(Json(RestSponsor::from(mongo_sponsor)).into_response())
 }
@@ -6,7 +6,7 @@ use serde_json::json;
 use uuid::Uuid;
 
 use crate::{AppResult, AppState};
-use crate::auth::User;
+use crate::auth::RequireEdit;
 use crate::error::AppError;
 use crate::models::mongo::{Change, ChangeType};
 
@@ -15,22 +15,19 @@ pub struct DeleteStruct {
     uid: Uuid,
 }
 
-pub async fn delete(state: State<AppState>, user: User, Json(ds): Json<DeleteStruct>) -> AppResult {
+pub async fn delete(state: State<AppState>, RequireEdit(user): RequireEdit, Json(ds): Json<DeleteStruct>) -> AppResult {
     let uid = ds.uid;
 
-    let Some(sponsor) = state.mongo.get(uid.into()).await? else {
+    let Some(sponsor) = state.store.get(uid.into()).await? else {
         return Err(AppError::new(400, "sponsor not found"));
     };
+    if !state.policy.may_edit(&user, &sponsor) {
+        return Err(AppError::new(403, "only owners can delete this sponsor"));
+    }
 
-    let favours = sponsor.favours.iter()
-        .map(|f| f.uid.into()).collect::<Vec<Uuid>>();
-
-    state.mongo.add_change(&Change::new(user.email, ChangeType::DeleteSponsor(sponsor))).await?;
-
-    state.mongo.delete_logo(&uid.into()).await?;
-    state.mongo.delete(&uid.into()).await?;
-    state.meili.delete_sponsor(&uid).await?;
-    state.meili.delete_favours(&favours).await?;
+    state.store.delete_logo(&uid.into()).await?;
+    state.store.delete(&uid.into()).await?;
+    state.store.add_change(&Change::new(user.email, ChangeType::DeleteSponsor(sponsor))).await?;
 
     Ok(Json(json!({})).into_response())
 }
@@ -14,7 +14,7 @@ use crate::models::rest::RestSponsor;
 pub async fn get(state: State<AppState>, _user: User, Path(sponsor_uid): Path<String>) -> AppResult {
     let uid = Uuid::from_str(&sponsor_uid)?;
 
-    match state.mongo.get(uid.into()).await? {
+    match state.store.get(uid.into()).await? {
         None => Err(AppError::new(404, "sponsor not found")),
         Some(sponsor) => Ok(Json(json!(RestSponsor::from(sponsor))).into_response())
     }
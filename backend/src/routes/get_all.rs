@@ -8,5 +8,5 @@ use crate::auth::User;
 use crate::models::rest::RestSponsor;
 
 pub async fn get_all(state: State<AppState>, _user: User) -> AppResult {
-    Ok(Json(json!(state.mongo.get_all().await?.into_iter().map(RestSponsor::from).collect::<Vec<_>>())).into_response())
+    Ok(Json(json!(state.store.get_all().await?.into_iter().map(RestSponsor::from).collect::<Vec<_>>())).into_response())
 }
@@ -1,9 +1,13 @@
+use axum::extract::State;
 use axum::Json;
 use axum::response::IntoResponse;
 use serde_json::json;
 
-use crate::AppResult;
+use crate::{AppResult, AppState};
 
-pub async fn healthcheck() -> AppResult {
-    Ok(Json(json!({"success": true})).into_response())
+pub async fn healthcheck(state: State<AppState>) -> AppResult {
+    // null if the oidc provider is not used
+    let oidc = state.oidc.as_ref().map(|oidc| oidc.status());
+
+    Ok(Json(json!({"success": true, "oidc": oidc})).into_response())
 }
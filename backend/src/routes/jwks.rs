@@ -0,0 +1,9 @@
+use axum::extract::State;
+use axum::Json;
+use axum::response::IntoResponse;
+
+use crate::{AppResult, AppState};
+
+pub async fn jwks(state: State<AppState>) -> AppResult {
+    Ok(Json(state.jwt.jwks()).into_response())
+}
@@ -1,10 +1,27 @@
-use axum::extract::State;
+use axum::extract::{Query, State};
 use axum::response::{IntoResponse, Redirect};
+use serde::Deserialize;
 
+use crate::error::AppError;
+use crate::misc::is_same_origin_path;
 use crate::{AppResult, AppState};
 
-pub async fn login(state: State<AppState>) -> AppResult {
-    let redirect_uri = state.oidc.create_auth_url().await;
+#[derive(Deserialize)]
+pub struct QueryParams {
+    redirect: Option<String>,
+}
+
+pub async fn login(state: State<AppState>, query: Query<QueryParams>) -> AppResult {
+    let Some(oidc) = &state.oidc else {
+        return Err(AppError::new(404, "oidc login is not enabled"));
+    };
+    if !oidc.is_ready() {
+        return Err(AppError::new(503, "identity provider not reachable yet, try again later"));
+    }
+    if query.redirect.as_deref().is_some_and(|path| !is_same_origin_path(path)) {
+        return Err(AppError::new(400, "redirect must be a path on the frontend"));
+    }
+    let redirect_uri = oidc.create_auth_url(query.0.redirect).await?;
 
     Ok(Redirect::temporary(&redirect_uri).into_response())
 }
@@ -1,11 +1,10 @@
 use axum::extract::{Query, State};
 use axum::http::header::SET_COOKIE;
-use axum::http::HeaderValue;
 use axum::response::{IntoResponse, Redirect};
 use serde::Deserialize;
 
-use crate::auth::OpenIdInstance;
-use crate::{AppResult, AppState};
+use crate::error::AppError;
+use crate::{session, AppResult, AppState};
 
 #[derive(Deserialize)]
 pub struct QueryParams {
@@ -14,21 +13,32 @@ pub struct QueryParams {
 }
 
 pub async fn login_code(state: State<AppState>, query: Query<QueryParams>) -> AppResult {
-    let claims = state
-        .oidc
+    let Some(oidc) = &state.oidc else {
+        return Err(AppError::new(404, "oidc login is not enabled"));
+    };
+    if !oidc.is_ready() {
+        return Err(AppError::new(503, "identity provider not reachable yet, try again later"));
+    }
+    let (claims, redirect) = oidc
         .fetch_token(query.code.clone(), query.state.clone())
         .await?;
-    let user = OpenIdInstance::user_from_claims(&state.mongo, claims).await?;
+    let user = oidc
+        .user_from_claims(state.store.as_ref(), &state.policy, claims)
+        .await?;
+    // the groups may have changed since the last login
+    state.roles.invalidate(&user.email).await;
+
+    let tokens = session::start_session(&state, user).await?;
 
-    let jwt = state.jwt.create_jwt(&user)?;
+    let target = match redirect {
+        Some(path) => format!("{}{}", state.config.frontend_url.trim_end_matches('/'), path),
+        None => state.config.frontend_url.clone(),
+    };
 
-    let mut response = Redirect::temporary(&state.config.frontend_url).into_response();
-    response.headers_mut().insert(
-        SET_COOKIE,
-        HeaderValue::from_str(
-            format!("session={}; Secure; Path=/; Max-Age=2592000", &jwt).as_str(),
-        )?,
-    );
+    let mut response = Redirect::temporary(&target).into_response();
+    for cookie in session::session_cookies(&tokens)? {
+        response.headers_mut().append(SET_COOKIE, cookie);
+    }
 
     Ok(response)
 }
@@ -0,0 +1,35 @@
+use axum::extract::State;
+use axum::http::header::SET_COOKIE;
+use axum::response::IntoResponse;
+use axum::Json;
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::auth::AuthProvider;
+use crate::error::AppError;
+use crate::{local_auth, session, AppResult, AppState};
+
+#[derive(Deserialize)]
+pub struct LoginLocal {
+    email: String,
+    password: String,
+}
+
+pub async fn login_local(state: State<AppState>, Json(body): Json<LoginLocal>) -> AppResult {
+    if state.config.auth_provider != AuthProvider::Local {
+        return Err(AppError::new(404, "local login is not enabled"));
+    }
+
+    let Some(user) = local_auth::login(&state, &body.email, &body.password).await? else {
+        return Err(AppError::new(401, "invalid email or password"));
+    };
+
+    let tokens = session::start_session(&state, user).await?;
+
+    let mut response = Json(json!({"token": &tokens.access_token, "csrfToken": &tokens.csrf_token})).into_response();
+    for cookie in session::session_cookies(&tokens)? {
+        response.headers_mut().append(SET_COOKIE, cookie);
+    }
+
+    Ok(response)
+}
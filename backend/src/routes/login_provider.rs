@@ -0,0 +1,16 @@
+use axum::extract::State;
+use axum::response::IntoResponse;
+use axum::Json;
+use serde_json::json;
+
+use crate::auth::AuthProvider;
+use crate::{AppResult, AppState};
+
+pub async fn login_provider(state: State<AppState>) -> AppResult {
+    let provider = match state.config.auth_provider {
+        AuthProvider::Oidc => "oidc",
+        AuthProvider::Local => "local",
+    };
+
+    Ok(Json(json!({"provider": provider})).into_response())
+}
@@ -0,0 +1,19 @@
+use axum::extract::State;
+use axum::http::header::SET_COOKIE;
+use axum::response::IntoResponse;
+use axum::Json;
+use serde_json::json;
+
+use crate::auth::User;
+use crate::{session, AppResult, AppState};
+
+pub async fn logout(state: State<AppState>, user: User) -> AppResult {
+    state.store.revoke_session(&user.sid.into()).await?;
+
+    let mut response = Json(json!({})).into_response();
+    for cookie in session::clear_session_cookies() {
+        response.headers_mut().append(SET_COOKIE, cookie);
+    }
+
+    Ok(response)
+}
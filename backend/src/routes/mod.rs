@@ -7,7 +7,29 @@ macro_rules! import_same_name {
     }
 }
 
-
 pub mod settings;
+pub mod tokens;
 
-import_same_name!(create, delete, get, get_all, get_logo, healthcheck, search, update, upload_logo, whoami, login, login_code, changes);
+import_same_name!(
+    create,
+    delete,
+    get,
+    get_all,
+    get_logo,
+    healthcheck,
+    search,
+    update,
+    upload_logo,
+    whoami,
+    login,
+    login_code,
+    changes,
+    refresh,
+    logout,
+    tick_favour,
+    my_sponsors,
+    login_local,
+    login_provider,
+    change_password,
+    jwks
+);
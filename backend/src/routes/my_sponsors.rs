@@ -0,0 +1,15 @@
+use axum::extract::State;
+use axum::Json;
+use axum::response::IntoResponse;
+use serde_json::json;
+
+use crate::{AppResult, AppState};
+use crate::auth::User;
+use crate::misc::normalize_email;
+use crate::models::rest::RestSponsor;
+
+pub async fn my_sponsors(state: State<AppState>, user: User) -> AppResult {
+    let sponsors = state.store.get_owned(&normalize_email(&user.email)).await?;
+
+    Ok(Json(json!(sponsors.into_iter().map(RestSponsor::from).collect::<Vec<_>>())).into_response())
+}
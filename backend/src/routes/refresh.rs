@@ -0,0 +1,26 @@
+use axum::extract::State;
+use axum::http::header::SET_COOKIE;
+use axum::http::HeaderMap;
+use axum::response::IntoResponse;
+use axum::Json;
+use serde_json::json;
+
+use crate::error::AppError;
+use crate::session::REFRESH_COOKIE;
+use crate::{session, AppResult, AppState};
+
+pub async fn refresh(state: State<AppState>, headers: HeaderMap) -> AppResult {
+    let Some(refresh_token) = session::get_cookie(&headers, REFRESH_COOKIE) else {
+        return Err(AppError::new(401, "no refresh token"));
+    };
+    let Some(tokens) = session::refresh_session(&state, refresh_token).await? else {
+        return Err(AppError::new(401, "invalid refresh token"));
+    };
+
+    let mut response = Json(json!({"token": &tokens.access_token, "csrfToken": &tokens.csrf_token})).into_response();
+    for cookie in session::session_cookies(&tokens)? {
+        response.headers_mut().append(SET_COOKIE, cookie);
+    }
+
+    Ok(response)
+}
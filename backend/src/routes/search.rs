@@ -1,5 +1,6 @@
 use std::collections::HashMap;
 
+use axum::extract::rejection::QueryRejection;
 use axum::extract::{Query, State};
 use axum::Json;
 use axum::response::IntoResponse;
@@ -8,28 +9,49 @@ use serde_json::json;
 use crate::{AppResult, AppState};
 use crate::auth::User;
 use crate::error::AppError;
-use crate::models::rest::{RestSponsor, RestSponsorFavour};
+use crate::models::rest::{RestSponsor, RestSponsorFavour, SearchKind, SearchQuery};
 
-pub async fn search(state: State<AppState>, _user: User, query: Query<HashMap<String, String>>) -> AppResult {
-    let Some(search) = query.get("search") else {
-        return Err(AppError::new(400, "no search query"));
-    };
-    let Some(typ) = query.get("type") else {
-        return Err(AppError::new(400, "no type query. must be sponsors or favours"));
-    };
+pub async fn search(
+    state: State<AppState>,
+    _user: User,
+    query: Result<Query<SearchQuery>, QueryRejection>,
+) -> AppResult {
+    let Query(query) = query.map_err(|e| AppError::new(400, e.body_text()))?;
+
+    let response = match query.kind {
+        SearchKind::Sponsors => {
+            let search = query.into_sponsor_search()?;
+
+            let result = state.index.get_sponsors(&search).await?;
+            let uids = result.hits.iter().map(|h| h.sponsor.id.into()).collect::<Vec<_>>();
+            let mut formatted = result.hits.into_iter()
+                .filter_map(|h| Some((h.sponsor.id, h.formatted?))).collect::<HashMap<_, _>>();
 
-    let returns = match typ.to_lowercase().as_str() {
-        "sponsors" => {
-            json!(futures::future::try_join_all(state.meili.get_sponsors(search).await?
+            let sponsors = state.store.get_many(&uids).await?
                 .into_iter()
-                .map(|x| state.mongo.get(x.id.into()))).await?
-                .into_iter().flatten().map(RestSponsor::from).collect::<Vec<_>>())
+                .map(|s| {
+                    let formatted = formatted.remove(&s.uid.into());
+                    RestSponsor { formatted, ..RestSponsor::from(s) }
+                })
+                .collect::<Vec<_>>();
+
+            json!({
+                "results": sponsors,
+                "estimatedTotalHits": result.estimated_total_hits,
+                "facets": {"tags": result.tag_facets},
+            })
         }
-        "favours" => {
-            json!(state.meili.get_favours(search).await?.into_iter().map(RestSponsorFavour::from).collect::<Vec<_>>())
+        SearchKind::Favours => {
+            let search = query.into_favour_search()?;
+
+            let result = state.index.get_favours(&search).await?;
+
+            json!({
+                "results": result.hits.into_iter().map(RestSponsorFavour::from).collect::<Vec<_>>(),
+                "estimatedTotalHits": result.estimated_total_hits,
+            })
         }
-        _ => return Err(AppError::new(400, "invalid type query"))
     };
 
-    Ok(Json(json!({"results": returns})).into_response())
+    Ok(Json(response).into_response())
 }
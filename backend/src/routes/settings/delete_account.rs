@@ -0,0 +1,56 @@
+use axum::extract::State;
+use axum::response::IntoResponse;
+use axum::Json;
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::auth::RequireAdmin;
+use crate::error::AppError;
+use crate::misc::normalize_email;
+use crate::models::mongo::{Change, ChangeType, UserRole};
+use crate::{AppResult, AppState};
+
+#[derive(Deserialize)]
+pub struct DeleteAccount {
+    email: String,
+}
+
+pub async fn delete_account(
+    state: State<AppState>,
+    RequireAdmin(user): RequireAdmin,
+    Json(body): Json<DeleteAccount>,
+) -> AppResult {
+    let email = normalize_email(&body.email);
+    if email == normalize_email(&user.email) {
+        return Err(AppError::new(400, "you cannot delete your own account"));
+    }
+    if !state.store.delete_local_account(&email).await? {
+        return Err(AppError::new(404, "account not found"));
+    }
+
+    state
+        .store
+        .add_change(&Change::new(
+            &user.email,
+            ChangeType::DeleteLocalAccount(email.clone()),
+        ))
+        .await?;
+    state.store.revoke_user_sessions(&email).await?;
+    state.store.delete_user_api_tokens(&email).await?;
+
+    // a new account with the same email must not inherit the role
+    if state.store.get_user_role(&email).await?.is_some() {
+        let role = UserRole {
+            email: email.clone(),
+            role: state.policy.default_role,
+        };
+        state
+            .store
+            .add_change(&Change::new(&user.email, ChangeType::ChangeUserRole(role)))
+            .await?;
+        state.store.delete_role(&email).await?;
+        state.roles.invalidate(&email).await;
+    }
+
+    Ok(Json(json!({})).into_response())
+}
@@ -1,11 +1,11 @@
 use axum::extract::State;
-use axum::Json;
 use axum::response::IntoResponse;
+use axum::Json;
 use serde_json::json;
 
-use crate::{AppResult, AppState};
 use crate::auth::User;
+use crate::{AppResult, AppState};
 
 pub async fn get(state: State<AppState>, _user: User) -> AppResult {
-    Ok(Json(json!(state.mongo.get_settings().await?)).into_response())
+    Ok(Json(json!(state.store.get_settings().await?)).into_response())
 }
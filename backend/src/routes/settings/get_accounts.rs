@@ -0,0 +1,18 @@
+use axum::extract::State;
+use axum::response::IntoResponse;
+use axum::Json;
+use serde_json::json;
+
+use crate::auth::RequireAdmin;
+use crate::{AppResult, AppState};
+
+pub async fn get_accounts(state: State<AppState>, _user: RequireAdmin) -> AppResult {
+    let mut accounts = state.store.get_local_accounts().await?;
+    accounts.sort_by(|a, b| a.email.cmp(&b.email));
+
+    Ok(Json(json!(accounts
+        .into_iter()
+        .map(|a| json!({"email": a.email, "name": a.name, "created": a.created}))
+        .collect::<Vec<_>>()))
+    .into_response())
+}
@@ -8,7 +8,7 @@ use crate::{AppResult, AppState};
 
 pub async fn get_admins(state: State<AppState>, _user: RequireAdmin) -> AppResult {
     Ok(Json(json!(state
-        .mongo
+        .store
         .get_all_admins()
         .await?
         .into_iter()
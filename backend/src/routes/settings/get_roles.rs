@@ -0,0 +1,18 @@
+use axum::extract::State;
+use axum::response::IntoResponse;
+use axum::Json;
+use serde_json::json;
+
+use crate::auth::RequireAdmin;
+use crate::{AppResult, AppState};
+
+pub async fn get_roles(state: State<AppState>, _user: RequireAdmin) -> AppResult {
+    let mut roles = state.store.get_all_roles().await?;
+    roles.sort_by(|a, b| a.email.cmp(&b.email));
+
+    Ok(Json(json!({
+        "roles": roles,
+        "defaultRole": state.policy.default_role,
+    }))
+    .into_response())
+}
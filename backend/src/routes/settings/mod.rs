@@ -1,9 +1,25 @@
+pub use delete_account::delete_account;
 pub use get::get;
+pub use get_accounts::get_accounts;
 pub use get_admins::get_admins;
+pub use get_roles::get_roles;
+pub use reindex::reindex;
+pub use revoke_sessions::revoke_sessions;
+pub use search_status::search_status;
 pub use update::update;
+pub use update_account::update_account;
 pub use update_admins::update_admins;
+pub use update_roles::update_roles;
 
+mod delete_account;
 mod get;
+mod get_accounts;
 mod get_admins;
+mod get_roles;
+mod reindex;
+mod revoke_sessions;
+mod search_status;
 mod update;
+mod update_account;
 mod update_admins;
+mod update_roles;
@@ -0,0 +1,19 @@
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::Json;
+use serde_json::json;
+
+use crate::auth::RequireAdmin;
+use crate::error::AppError;
+use crate::{meili_sync, AppResult, AppState};
+
+pub async fn reindex(state: State<AppState>, _user: RequireAdmin) -> AppResult {
+    if !meili_sync::trigger_run(state.0.clone()) {
+        return Err(AppError::new(409, "reindex already running"));
+    }
+
+    let status = state.sync_status.lock().unwrap().clone();
+
+    Ok((StatusCode::ACCEPTED, Json(json!(status))).into_response())
+}
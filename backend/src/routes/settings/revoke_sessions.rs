@@ -0,0 +1,33 @@
+use axum::extract::State;
+use axum::response::IntoResponse;
+use axum::Json;
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::auth::RequireAdmin;
+use crate::misc::normalize_email;
+use crate::models::mongo::{Change, ChangeType};
+use crate::{AppResult, AppState};
+
+#[derive(Deserialize)]
+pub struct RevokeSessions {
+    email: String,
+}
+
+pub async fn revoke_sessions(
+    state: State<AppState>,
+    RequireAdmin(user): RequireAdmin,
+    Json(body): Json<RevokeSessions>,
+) -> AppResult {
+    let email = normalize_email(&body.email);
+    state
+        .store
+        .add_change(&Change::new(
+            user.email,
+            ChangeType::RevokeSessions(email.clone()),
+        ))
+        .await?;
+    let revoked = state.store.revoke_user_sessions(&email).await?;
+
+    Ok(Json(json!({"revoked": revoked})).into_response())
+}
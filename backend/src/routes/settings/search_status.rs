@@ -0,0 +1,13 @@
+use axum::extract::State;
+use axum::response::IntoResponse;
+use axum::Json;
+use serde_json::json;
+
+use crate::auth::RequireAdmin;
+use crate::{AppResult, AppState};
+
+pub async fn search_status(state: State<AppState>, _user: RequireAdmin) -> AppResult {
+    let status = state.sync_status.lock().unwrap().clone();
+
+    Ok(Json(json!(status)).into_response())
+}
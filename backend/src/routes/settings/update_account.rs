@@ -0,0 +1,58 @@
+use axum::extract::State;
+use axum::response::IntoResponse;
+use axum::Json;
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::auth::RequireAdmin;
+use crate::error::AppError;
+use crate::local_auth::MIN_PASSWORD_LENGTH;
+use crate::models::mongo::{Change, ChangeType};
+use crate::{local_auth, AppResult, AppState};
+
+#[derive(Deserialize)]
+pub struct UpdateAccount {
+    email: String,
+    name: String,
+    password: String,
+}
+
+pub async fn update_account(
+    state: State<AppState>,
+    RequireAdmin(user): RequireAdmin,
+    Json(body): Json<UpdateAccount>,
+) -> AppResult {
+    if !body.email.contains('@') {
+        return Err(AppError::new(400, "invalid email"));
+    }
+    if body.name.trim().is_empty() {
+        return Err(AppError::new(400, "name must not be empty"));
+    }
+    if body.password.len() < MIN_PASSWORD_LENGTH {
+        return Err(AppError::new(
+            400,
+            format!(
+                "password must be at least {} characters",
+                MIN_PASSWORD_LENGTH
+            ),
+        ));
+    }
+
+    let account = local_auth::set_account(
+        state.store.as_ref(),
+        &body.email,
+        &body.name,
+        &body.password,
+    )
+    .await?;
+    state
+        .store
+        .add_change(&Change::new(
+            user.email,
+            ChangeType::ChangeLocalAccount(account.email.clone()),
+        ))
+        .await?;
+    state.store.revoke_user_sessions(&account.email).await?;
+
+    Ok(Json(json!({})).into_response())
+}
@@ -7,12 +7,16 @@ use serde::Deserialize;
 use serde_json::json;
 
 use crate::auth::{RequireAdmin, Role};
+use crate::error::AppError;
+use crate::misc::normalize_email;
 use crate::models::mongo::{Change, ChangeType, UserRole};
 use crate::{AppResult, AppState};
 
 #[derive(Deserialize)]
 pub struct UpdateAdmins {
     admins: Vec<String>,
+    #[serde(default, rename = "confirmSelfDemotion")]
+    confirm_self_demotion: bool,
 }
 
 pub async fn update_admins(
@@ -21,7 +25,7 @@ pub async fn update_admins(
     Json(body): Json<UpdateAdmins>,
 ) -> AppResult {
     let db_admins: HashSet<UserRole> = state
-        .mongo
+        .store
         .get_all_admins()
         .await?
         .into_iter()
@@ -30,18 +34,34 @@ pub async fn update_admins(
     let req_admins: HashSet<UserRole> = body
         .admins
         .into_iter()
+        .map(|email| normalize_email(&email))
+        .filter(|email| !email.is_empty())
         .map(|email| UserRole {
             email,
             role: Role::ADMIN,
         })
         .collect();
 
+    if req_admins.is_empty() {
+        return Err(AppError::new(400, "at least one admin must remain"));
+    }
+    if !body.confirm_self_demotion
+        && !req_admins
+            .iter()
+            .any(|r| r.email == normalize_email(&user.email))
+    {
+        return Err(AppError::new(
+            409,
+            "you are removing your own admin role, confirm with confirmSelfDemotion",
+        ));
+    }
+
     let to_add = req_admins.difference(&db_admins);
     let to_remove = db_admins.difference(&req_admins);
 
     for role in req_admins.symmetric_difference(&db_admins) {
         state
-            .mongo
+            .store
             .add_change(&Change::new(
                 &user.email,
                 ChangeType::ChangeUserRole(role.clone()),
@@ -50,17 +70,13 @@ pub async fn update_admins(
     }
 
     for role in to_add {
-        state.mongo.add_or_update_role(role).await?;
+        state.store.add_or_update_role(role).await?;
+        state.roles.invalidate(&role.email).await;
     }
 
     for role in to_remove {
-        state
-            .mongo
-            .add_or_update_role(&UserRole {
-                email: role.email.clone(),
-                role: Role::USER,
-            })
-            .await?;
+        state.store.delete_role(&role.email).await?;
+        state.roles.invalidate(&role.email).await;
     }
 
     Ok(Json(json!({})).into_response())
@@ -0,0 +1,106 @@
+use std::collections::HashMap;
+
+use axum::extract::State;
+use axum::response::IntoResponse;
+use axum::Json;
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::auth::{RequireAdmin, Role};
+use crate::error::AppError;
+use crate::misc::normalize_email;
+use crate::models::mongo::{Change, ChangeType, GroupRole, UserRole};
+use crate::{AppResult, AppState};
+
+#[derive(Deserialize)]
+pub struct UpdateRoles {
+    roles: Vec<UserRole>,
+    #[serde(default, rename = "confirmSelfDemotion")]
+    confirm_self_demotion: bool,
+}
+
+pub async fn update_roles(
+    state: State<AppState>,
+    RequireAdmin(user): RequireAdmin,
+    Json(body): Json<UpdateRoles>,
+) -> AppResult {
+    let mut req_roles: HashMap<String, Role> = HashMap::new();
+    for role in body.roles {
+        let email = normalize_email(&role.email);
+        if email.is_empty() {
+            continue;
+        }
+        if req_roles.insert(email, role.role).is_some() {
+            return Err(AppError::new(400, "every email may only be listed once"));
+        }
+    }
+    let db_roles: HashMap<String, Role> = state
+        .store
+        .get_all_roles()
+        .await?
+        .into_iter()
+        .map(|r| (r.email, r.role))
+        .collect();
+
+    // resolved like RoleCache::resolve, with the requested assignments in place of the stored ones
+    let email = normalize_email(&user.email);
+    let own_role = match req_roles.get(&email) {
+        Some(role) => *role,
+        None => state
+            .store
+            .get_group_role(&email)
+            .await?
+            .filter(GroupRole::is_fresh)
+            .map_or(state.policy.default_role, |g| g.role),
+    };
+    if own_role != Role::ADMIN
+        && state.policy.default_role != Role::ADMIN
+        && !req_roles.values().any(|r| *r == Role::ADMIN)
+    {
+        return Err(AppError::new(400, "at least one admin must remain"));
+    }
+    if !body.confirm_self_demotion && own_role != Role::ADMIN {
+        return Err(AppError::new(
+            409,
+            "you are removing your own admin role, confirm with confirmSelfDemotion",
+        ));
+    }
+
+    for (email, role) in req_roles.iter() {
+        if db_roles.get(email) == Some(role) {
+            continue;
+        }
+
+        let role = UserRole {
+            email: email.clone(),
+            role: *role,
+        };
+        state
+            .store
+            .add_change(&Change::new(
+                &user.email,
+                ChangeType::ChangeUserRole(role.clone()),
+            ))
+            .await?;
+        state.store.add_or_update_role(&role).await?;
+        state.roles.invalidate(email).await;
+    }
+
+    for email in db_roles
+        .keys()
+        .filter(|email| !req_roles.contains_key(*email))
+    {
+        let role = UserRole {
+            email: email.clone(),
+            role: state.policy.default_role,
+        };
+        state
+            .store
+            .add_change(&Change::new(&user.email, ChangeType::ChangeUserRole(role)))
+            .await?;
+        state.store.delete_role(email).await?;
+        state.roles.invalidate(email).await;
+    }
+
+    Ok(Json(json!({})).into_response())
+}
@@ -0,0 +1,47 @@
+use axum::extract::State;
+use axum::response::IntoResponse;
+use axum::Json;
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::auth::RequireTickFavours;
+use crate::error::AppError;
+use crate::models::mongo::{Change, ChangeType, SponsorFavour};
+use crate::models::rest::RestSponsor;
+use crate::{AppResult, AppState};
+
+#[derive(Deserialize)]
+pub struct TickFavour {
+    #[serde(rename = "sponsorUid")]
+    sponsor_uid: Uuid,
+    uid: Uuid,
+    completed: bool,
+}
+
+// not restricted by OWNERS_ONLY_EDIT, favour managers tick favours of every sponsor
+pub async fn tick_favour(
+    state: State<AppState>,
+    RequireTickFavours(user): RequireTickFavours,
+    Json(body): Json<TickFavour>,
+) -> AppResult {
+    let Some(mut sponsor) = state.store.get(body.sponsor_uid.into()).await? else {
+        return Err(AppError::new(400, "sponsor not found"));
+    };
+    let Some(favour) = sponsor
+        .favours
+        .iter_mut()
+        .find(|f| f.uid == body.uid.into())
+    else {
+        return Err(AppError::new(400, "favour not found"));
+    };
+    favour.completed_at = SponsorFavour::completed_at(body.completed, Some(favour));
+    favour.completed = body.completed;
+
+    state.store.update(&sponsor.uid, &sponsor).await?;
+    state
+        .store
+        .add_change(&Change::new(user.email, ChangeType::ChangeSponsor(sponsor.clone())))
+        .await?;
+
+    Ok(Json(RestSponsor::from(sponsor)).into_response())
+}
@@ -0,0 +1,65 @@
+use axum::extract::State;
+use axum::response::IntoResponse;
+use axum::Json;
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::api_token::{create_api_token, MAX_TOKEN_LIFETIME_DAYS};
+use crate::auth::{Role, User};
+use crate::error::AppError;
+use crate::models::mongo::TokenScope;
+use crate::models::rest::RestApiToken;
+use crate::{AppResult, AppState};
+
+#[derive(Deserialize)]
+pub struct CreateToken {
+    name: String,
+    scope: TokenScope,
+    #[serde(rename = "expiresInDays")]
+    expires_in_days: Option<u32>,
+}
+
+pub async fn create(
+    state: State<AppState>,
+    user: User,
+    Json(body): Json<CreateToken>,
+) -> AppResult {
+    if user.token.is_some() {
+        return Err(AppError::new(403, "api tokens cannot create api tokens"));
+    }
+
+    let name = body.name.trim().to_string();
+    if name.is_empty() {
+        return Err(AppError::new(400, "name must not be empty"));
+    }
+    if body
+        .expires_in_days
+        .is_some_and(|days| !(1..=MAX_TOKEN_LIFETIME_DAYS).contains(&days))
+    {
+        return Err(AppError::new(
+            400,
+            format!(
+                "expiresInDays must be between 1 and {}",
+                MAX_TOKEN_LIFETIME_DAYS
+            ),
+        ));
+    }
+    if body.scope == TokenScope::ADMIN && user.role != Role::ADMIN {
+        return Err(AppError::new(403, "only admins can create admin tokens"));
+    }
+
+    let (token, secret) = create_api_token(
+        &state,
+        &user,
+        name,
+        body.scope,
+        body.expires_in_days.map(i64::from),
+    )
+    .await?;
+
+    Ok(Json(json!({
+        "token": secret,
+        "apiToken": RestApiToken::from(token),
+    }))
+    .into_response())
+}
@@ -0,0 +1,19 @@
+use axum::extract::State;
+use axum::response::IntoResponse;
+use axum::Json;
+
+use crate::auth::User;
+use crate::models::rest::RestApiToken;
+use crate::{AppResult, AppState};
+
+pub async fn list(state: State<AppState>, user: User) -> AppResult {
+    let tokens = state
+        .store
+        .get_api_tokens(&user.email)
+        .await?
+        .into_iter()
+        .map(RestApiToken::from)
+        .collect::<Vec<_>>();
+
+    Ok(Json(tokens).into_response())
+}
@@ -0,0 +1,7 @@
+pub use create::create;
+pub use list::list;
+pub use revoke::revoke;
+
+mod create;
+mod list;
+mod revoke;
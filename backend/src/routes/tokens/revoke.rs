@@ -0,0 +1,31 @@
+use axum::extract::State;
+use axum::response::IntoResponse;
+use axum::Json;
+use serde::Deserialize;
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::auth::User;
+use crate::error::AppError;
+use crate::{AppResult, AppState};
+
+#[derive(Deserialize)]
+pub struct RevokeToken {
+    uid: Uuid,
+}
+
+pub async fn revoke(
+    state: State<AppState>,
+    user: User,
+    Json(body): Json<RevokeToken>,
+) -> AppResult {
+    if !state
+        .store
+        .delete_api_token(&body.uid.into(), &user.email)
+        .await?
+    {
+        return Err(AppError::new(404, "token not found"));
+    }
+
+    Ok(Json(json!({})).into_response())
+}
@@ -4,13 +4,12 @@ use axum::response::IntoResponse;
 use uuid::Uuid;
 
 use crate::{AppResult, AppState, misc};
-use crate::auth::User;
+use crate::auth::RequireEdit;
 use crate::error::AppError;
-use crate::models::meili::MeiliSponsorFavour;
 use crate::models::mongo::{Change, ChangeType, Sponsor, SponsorFavour, SponsorField};
 use crate::models::rest::RestSponsor;
 
-pub async fn update(state: State<AppState>, user: User, payload: Json<RestSponsor>) -> AppResult {
+pub async fn update(state: State<AppState>, RequireEdit(user): RequireEdit, payload: Json<RestSponsor>) -> AppResult {
     let mut payload = payload.0;
 
     payload.favours.iter_mut().
@@ -19,6 +18,18 @@ pub async fn update(state: State<AppState>, user: User, payload: Json<RestSponso
             favour.uid = Some(Uuid::new_v4());
         });
 
+    let Some(uid) = payload.uid else { return Err(AppError::new(400, "uid missing")); };
+    let Some(existing) = state.store.get(uid.into()).await? else { return Err(AppError::new(400, "sponsor not found")); };
+    if !state.policy.may_edit(&user, &existing) {
+        return Err(AppError::new(403, "only owners can edit this sponsor"));
+    }
+
+    let owners = match payload.owners.map(misc::normalize_emails) {
+        Some(owners) if owners.is_empty() => return Err(AppError::new(400, "a sponsor needs at least one owner")),
+        Some(owners) => owners,
+        None => existing.owners,
+    };
+
     let mongo_sponsor = Sponsor {
         uid: payload.uid.unwrap().into(),
         name: payload.name,
@@ -26,12 +37,17 @@ pub async fn update(state: State<AppState>, user: User, payload: Json<RestSponso
         image_url: payload.image_url,
         fields: payload.fields.into_iter().map(|field| SponsorField { name: field.name, value: field.value }).collect(),
         tags: payload.tags,
+        owners,
         favours: payload.favours.into_iter().map(|favour| SponsorFavour {
             uid: favour.uid.unwrap().into(),
             sponsor_uid: payload.uid.unwrap().into(),
             condition: favour.condition,
             completed: favour.completed,
             due_until: favour.due_until,
+            completed_at: SponsorFavour::completed_at(
+                favour.completed,
+                existing.favours.iter().find(|f| Some(f.uid.into()) == favour.uid),
+            ),
         }).collect(),
     };
 
@@ -39,17 +55,11 @@ pub async fn update(state: State<AppState>, user: User, payload: Json<RestSponso
         return Err(AppError::new(400, e.to_string()));
     }
 
-    state.mongo.add_change(&Change::new(user.email, ChangeType::ChangeSponsor(mongo_sponsor.clone()))).await?;
-
-    state.mongo.update(&mongo_sponsor.uid, &mongo_sponsor).await?;
+    state.store.update(&mongo_sponsor.uid, &mongo_sponsor).await?;
+    state.store.add_change(&Change::new(user.email, ChangeType::ChangeSponsor(mongo_sponsor.clone()))).await?;
     if mongo_sponsor.image_url.is_none() {
-        let _ = state.mongo.delete_logo(&mongo_sponsor.uid).await; // ignore errors
+        let _ = state.store.delete_logo(&mongo_sponsor.uid).await; // ignore errors
     }
 
-    state.meili.insert_sponsor(&mongo_sponsor.clone().into()).await?;
-    state.meili.insert_favours(
-        &MeiliSponsorFavour::from_sponsor_vec(&mongo_sponsor.favours)
-    ).await?;
-
     Ok(Json(RestSponsor::from(mongo_sponsor)).into_response())
 }
@@ -6,12 +6,12 @@ use axum::response::IntoResponse;
 use serde_json::json;
 
 use crate::{AppResult, AppState};
-use crate::auth::User;
+use crate::auth::RequireEdit;
 use crate::error::AppError;
 use crate::models::mongo::{Change, ChangeType};
 use crate::models::rest::RestSponsor;
 
-pub async fn upload_logo(state: State<AppState>, user: User, mut multipart: Multipart) -> AppResult {
+pub async fn upload_logo(state: State<AppState>, RequireEdit(user): RequireEdit, mut multipart: Multipart) -> AppResult {
     let mut sponsor_uid = None;
     let mut logo_data = None;
 
@@ -42,13 +42,15 @@ pub async fn upload_logo(state: State<AppState>, user: User, mut multipart: Mult
     let sponsor_uid = uuid::Uuid::from_str(&sponsor_uid.unwrap())?;
     let mongo_uid = sponsor_uid.into();
 
-    let Some(mut sponsor) = state.mongo.get(mongo_uid).await? else { return Err(AppError::new(400, "sponsor not found??")); };
-
-    state.mongo.add_change(&Change::new(user.email, ChangeType::ChangeLogo(sponsor.clone()))).await?;
+    let Some(mut sponsor) = state.store.get(mongo_uid).await? else { return Err(AppError::new(400, "sponsor not found??")); };
+    if !state.policy.may_edit(&user, &sponsor) {
+        return Err(AppError::new(403, "only owners can edit this sponsor"));
+    }
 
-    state.mongo.upload_logo(&mongo_uid, logo_data).await?;
+    state.store.upload_logo(&mongo_uid, logo_data).await?;
     sponsor.image_url = Some(format!("/get_logo/{}", &sponsor_uid.to_string()));
-    state.mongo.update(&mongo_uid, &sponsor).await?;
+    state.store.update(&mongo_uid, &sponsor).await?;
+    state.store.add_change(&Change::new(user.email, ChangeType::ChangeLogo(sponsor.clone()))).await?;
 
     Ok(Json(json!(RestSponsor::from(sponsor))).into_response())
 }
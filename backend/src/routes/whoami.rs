@@ -1,10 +1,18 @@
+use axum::http::HeaderMap;
 use axum::Json;
 use axum::response::IntoResponse;
 use serde_json::json;
 
 use crate::AppResult;
 use crate::auth::User;
+use crate::session::{self, CSRF_COOKIE};
 
-pub async fn whoami(user: User) -> AppResult {
-    Ok(Json(json!(user)).into_response())
+pub async fn whoami(user: User, headers: HeaderMap) -> AppResult {
+    let mut body = json!(user);
+    // after the OIDC redirect this is the only way for a frontend on another origin to get it
+    if let Some(csrf_token) = session::get_cookie(&headers, CSRF_COOKIE) {
+        body["csrfToken"] = json!(csrf_token);
+    }
+
+    Ok(Json(body).into_response())
 }
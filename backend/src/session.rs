@@ -0,0 +1,172 @@
+use std::str::FromStr;
+
+use axum::http::header::COOKIE;
+use axum::http::{HeaderMap, HeaderValue};
+use chrono::{Duration, Utc};
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::auth::User;
+use crate::models::mongo::Session;
+use crate::AppStateStruct;
+
+pub const ACCESS_TOKEN_LIFETIME_MINUTES: i64 = 15;
+pub const SESSION_LIFETIME_DAYS: i64 = 30;
+
+pub const SESSION_COOKIE: &str = "session";
+pub const REFRESH_COOKIE: &str = "refresh";
+pub const CSRF_COOKIE: &str = "csrf";
+pub const CSRF_HEADER: &str = "X-CSRF-Token";
+
+pub struct SessionTokens {
+    pub access_token: String,
+    pub refresh_token: String,
+    pub csrf_token: String,
+}
+
+pub async fn start_session(
+    state: &AppStateStruct,
+    mut user: User,
+) -> anyhow::Result<SessionTokens> {
+    let (secret, hash) = new_refresh_secret();
+    let now = Utc::now();
+    let session = Session {
+        uid: Uuid::new_v4().into(),
+        email: user.email.clone(),
+        sub: user.sub.clone(),
+        dn: user.dn.clone(),
+        refresh_token_hash: hash,
+        created: now,
+        expires: now + Duration::days(SESSION_LIFETIME_DAYS),
+        revoked: false,
+    };
+    state.store.create_session(&session).await?;
+
+    user.sid = session.uid.into();
+    user.exp = access_token_expiry();
+
+    Ok(SessionTokens {
+        access_token: state.jwt.create_jwt(&user)?,
+        refresh_token: format!("{}.{}", session.uid, secret),
+        csrf_token: random_token(),
+    })
+}
+
+pub async fn refresh_session(
+    state: &AppStateStruct,
+    refresh_token: &str,
+) -> anyhow::Result<Option<SessionTokens>> {
+    let Some((sid, secret)) = refresh_token.split_once('.') else {
+        return Ok(None);
+    };
+    let Ok(sid) = Uuid::from_str(sid) else {
+        return Ok(None);
+    };
+
+    let (new_secret, new_hash) = new_refresh_secret();
+    let Some(session) = state
+        .store
+        .rotate_session(&sid.into(), &hash_secret(secret), &new_hash)
+        .await?
+    else {
+        return Ok(None);
+    };
+    // the groups are only read at login, make the user log in again once they may have changed
+    if state
+        .store
+        .get_group_role(&session.email)
+        .await?
+        .is_some_and(|g| !g.is_fresh())
+    {
+        return Ok(None);
+    }
+
+    let role = state.roles.resolve(state, &session.email).await?;
+    let user = User {
+        sub: session.sub,
+        email: session.email,
+        dn: session.dn,
+        exp: access_token_expiry(),
+        role,
+        sid,
+        token: None,
+    };
+
+    Ok(Some(SessionTokens {
+        access_token: state.jwt.create_jwt(&user)?,
+        refresh_token: format!("{}.{}", sid, new_secret),
+        csrf_token: random_token(),
+    }))
+}
+
+pub fn session_cookies(tokens: &SessionTokens) -> anyhow::Result<[HeaderValue; 3]> {
+    let max_age = Duration::days(SESSION_LIFETIME_DAYS).num_seconds();
+    Ok([
+        HeaderValue::from_str(&format!(
+            "{}={}; Secure; HttpOnly; SameSite=Lax; Path=/api; Max-Age={}",
+            SESSION_COOKIE, tokens.access_token, max_age
+        ))?,
+        HeaderValue::from_str(&format!(
+            "{}={}; Secure; HttpOnly; SameSite=Strict; Path=/api; Max-Age={}",
+            REFRESH_COOKIE, tokens.refresh_token, max_age
+        ))?,
+        HeaderValue::from_str(&format!(
+            "{}={}; Secure; SameSite=Strict; Path=/; Max-Age={}",
+            CSRF_COOKIE, tokens.csrf_token, max_age
+        ))?,
+    ])
+}
+
+pub fn clear_session_cookies() -> [HeaderValue; 3] {
+    [
+        HeaderValue::from_static("session=; Secure; HttpOnly; SameSite=Lax; Path=/api; Max-Age=0"),
+        HeaderValue::from_static(
+            "refresh=; Secure; HttpOnly; SameSite=Strict; Path=/api; Max-Age=0",
+        ),
+        HeaderValue::from_static("csrf=; Secure; SameSite=Strict; Path=/; Max-Age=0"),
+    ]
+}
+
+// double submit, a cross site request carries the cookie but cannot read it
+pub fn csrf_valid(headers: &HeaderMap) -> bool {
+    let Some(cookie) = get_cookie(headers, CSRF_COOKIE) else {
+        return false;
+    };
+    let Some(header) = headers.get(CSRF_HEADER).and_then(|h| h.to_str().ok()) else {
+        return false;
+    };
+
+    !cookie.is_empty() && cookie == header
+}
+
+pub fn get_cookie<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers
+        .get_all(COOKIE)
+        .iter()
+        .filter_map(|h| h.to_str().ok())
+        .flat_map(|h| h.split(';'))
+        .filter_map(|c| c.trim().split_once('='))
+        .find(|(n, _)| *n == name)
+        .map(|(_, v)| v)
+}
+
+fn access_token_expiry() -> usize {
+    (Utc::now() + Duration::minutes(ACCESS_TOKEN_LIFETIME_MINUTES)).timestamp() as usize
+}
+
+fn new_refresh_secret() -> (String, String) {
+    let secret = random_token();
+    let hash = hash_secret(&secret);
+    (secret, hash)
+}
+
+pub(crate) fn random_token() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+pub(crate) fn hash_secret(secret: &str) -> String {
+    hex::encode(Sha256::digest(secret.as_bytes()))
+}
@@ -4,7 +4,7 @@ export default defineNuxtRouteMiddleware(async (to, from) => {
   await authStore.fetchUser();
 
   if (to.path !== "/login" && authStore.user === null)
-    return navigateTo("/login");
+    return navigateTo({ path: "/login", query: { redirect: to.fullPath } });
 
   if (to.path === "login" && authStore.user !== null) return abortNavigation();
 });
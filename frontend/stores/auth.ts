@@ -3,10 +3,8 @@ import { getHttpClient } from "~/utils/http";
 
 export const useAuthStore = defineStore("user", () => {
   const user: Ref<Auth | null> = ref(null);
-  const sessionCookie = useCookie("session", {
-    secure: import.meta.env.PROD,
-    expires: getSessionCookieExpiry(),
-  });
+  // the csrf cookie is not readable if the api is served from another origin
+  const csrfToken: Ref<string | null> = ref(null);
 
   async function fetchUser() {
     if (user.value !== null) return;
@@ -14,7 +12,9 @@ export const useAuthStore = defineStore("user", () => {
     const response = await getHttpClient(false, false).get("/whoami");
     if (!response) return;
 
-    user.value = await response.data;
+    const { csrfToken: token, ...auth } = response.data;
+    user.value = auth;
+    if (token) csrfToken.value = token;
   }
 
   async function login(email: string, password: string) {
@@ -22,22 +22,49 @@ export const useAuthStore = defineStore("user", () => {
       email: email,
       password: password,
     });
-    const data = await res.data;
+    if (!res) return;
 
-    sessionCookie.value = data.token;
+    csrfToken.value = res.data.csrfToken;
     await fetchUser();
   }
 
-  function logout() {
+  async function logout() {
+    await getHttpClient(false, false).post("/logout");
     user.value = null;
-    sessionCookie.value = null;
+    csrfToken.value = null;
+  }
+
+  async function refresh(): Promise<boolean> {
+    const res = await getHttpClient(false, false, false).post("/refresh");
+    if (!res) return false;
+
+    csrfToken.value = res.data.csrfToken;
+    return true;
   }
 
   function isAdmin(): boolean {
     return user.value?.role === "ADMIN";
   }
 
-  return { user, fetchUser, login, logout, isAdmin, sessionCookie };
+  function canEdit(): boolean {
+    return user.value?.role === "USER" || isAdmin();
+  }
+
+  function canTickFavours(): boolean {
+    return user.value?.role === "FAVOUR_MANAGER" || canEdit();
+  }
+
+  return {
+    user,
+    csrfToken,
+    fetchUser,
+    login,
+    logout,
+    refresh,
+    isAdmin,
+    canEdit,
+    canTickFavours,
+  };
 });
 
 export interface Auth {
@@ -45,16 +72,10 @@ export interface Auth {
   email: String;
   exp: number;
   dn: String;
-  role: "USER" | "ADMIN";
+  role: "VIEWER" | "FAVOUR_MANAGER" | "USER" | "ADMIN";
+  sid: String;
 }
 
 if (import.meta.hot) {
   import.meta.hot.accept(acceptHMRUpdate(useAuthStore, import.meta.hot));
 }
-
-function getSessionCookieExpiry(): Date {
-  const date = new Date();
-  date.setDate(date.getDate() + 31);
-
-  return date;
-}
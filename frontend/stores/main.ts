@@ -64,6 +64,22 @@ export const useMainStore = defineStore("main", () => {
     return data;
   }
 
+  // only changes the completion, also allowed for favour managers
+  async function tickFavour(
+    favour: SponsorFavour,
+    completed: boolean
+  ): Promise<Sponsor> {
+    const res = await getHttpClient().post("/tick_favour", {
+      sponsorUid: favour.sponsorUid,
+      uid: favour.uid,
+      completed: completed,
+    });
+    const data = (await res.data) as Sponsor;
+
+    _replaceSponsor(data);
+    return data;
+  }
+
   async function deleteSponsor(sponsor: Sponsor) {
     await getHttpClient().post("/delete", { uid: sponsor.uid });
     sponsors.value = sponsors.value.filter((s) => s.uid !== sponsor.uid);
@@ -80,6 +96,7 @@ export const useMainStore = defineStore("main", () => {
     fetchSettings,
     saveSettings,
     createOrUpdateSponsor,
+    tickFavour,
     deleteSponsor,
     settings,
     sponsors,
@@ -3,21 +3,24 @@ import { getLoadingBar, getNotificationApi } from "~/utils/misc";
 
 export function getHttpClient(
   addErrorInterceptor: boolean = true,
-  loadingBar: boolean = true
+  loadingBar: boolean = true,
+  refreshOnUnauthorized: boolean = true
 ): AxiosInstance {
   // @ts-ignore
   const { apiEndpoint } = useAppConfig();
   const authStore = useAuthStore();
+  const csrfCookie = useCookie("csrf");
 
   const instance = axios.create({
     baseURL: apiEndpoint,
-    headers: {
-      Authorization: `Bearer ${authStore.sessionCookie}`,
-    },
+    withCredentials: true,
   });
 
   instance.interceptors.request.use((config) => {
     if (loadingBar) getLoadingBar().start();
+    // the session cookie is HttpOnly, state changing requests have to echo the csrf cookie
+    const csrfToken = authStore.csrfToken ?? csrfCookie.value;
+    if (csrfToken) config.headers["X-CSRF-Token"] = csrfToken;
     return config;
   });
 
@@ -26,7 +29,19 @@ export function getHttpClient(
       if (loadingBar) getLoadingBar().finish();
       return res;
     },
-    (err: AxiosError) => {
+    async (err: AxiosError) => {
+      // access tokens are short-lived, get a new one and retry once
+      if (
+        refreshOnUnauthorized &&
+        err.response?.status === 401 &&
+        err.config &&
+        (await authStore.refresh())
+      ) {
+        return getHttpClient(addErrorInterceptor, loadingBar, false).request(
+          err.config
+        );
+      }
+
       if (loadingBar) getLoadingBar().error();
       console.error(`Error while sending request`, err);
 
@@ -7,6 +7,7 @@ export interface Sponsor {
   tags: string[];
   favoursCompleted: boolean;
   favours: SponsorFavour[];
+  owners?: string[];
 }
 
 export interface SponsorField {
@@ -20,4 +21,5 @@ export interface SponsorFavour {
   condition: string;
   completed: boolean;
   dueUntil: string;
+  completedAt?: string;
 }